@@ -1,5 +1,8 @@
 use {
-    crate::Result,
+    crate::{
+        log::{Level, Rotation},
+        Result,
+    },
     directories::ProjectDirs,
     linked_hash_map::LinkedHashMap,
     serde::{Deserialize, Serialize},
@@ -8,6 +11,7 @@ use {
         fs::{self, File},
         io::{BufReader, BufWriter, Write},
         path::{Path, PathBuf},
+        time::Duration,
     },
 };
 
@@ -15,7 +19,53 @@ use {
 pub struct GenericConfig<E> {
     #[serde(default = "default_sensitivity")]
     pub default_sensitivity: f64,
+    /// applied instead of the default while nothing or the shell is focused
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub desktop: Option<f64>,
+    /// applied instead of the default while a process without an applicable
+    /// entry is focused
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub unmatched: Option<f64>,
+    /// run when the default sensitivity starts being applied
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub default_on_enter: Option<String>,
+    /// run when the default sensitivity stops being applied
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub default_on_exit: Option<String>,
+    /// a script that decides the sensitivity instead of the entries, relative
+    /// to the config directory
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub script: Option<PathBuf>,
+    /// how long the script may run each time before the entries are used
+    #[serde(
+        default = "default_script_timeout_ms",
+        skip_serializing_if = "is_default_script_timeout"
+    )]
+    pub script_timeout_ms: u64,
+    /// how long no input has to be received before the default sensitivity is
+    /// applied, until the next input
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub idle_timeout_secs: Option<u64>,
+    /// how long commands may run before they're killed
+    #[serde(
+        default = "default_command_timeout_secs",
+        skip_serializing_if = "is_default_command_timeout"
+    )]
+    pub command_timeout_secs: u64,
     pub processes: E,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub logs: Logs,
+    /// the lowest level of messages written to the output. everything is
+    /// logged regardless.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub log_level: Level,
+    /// the HTTP server, which is disabled if not specified. only read when
+    /// senscale starts.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub http: Option<Http>,
+    /// actions by key combination, like `Ctrl+Alt+Up`
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub hotkeys: LinkedHashMap<String, Action>,
 }
 
 pub type Config = GenericConfig<HashMap<PathBuf, Entry>>;
@@ -25,7 +75,19 @@ impl<E: Default> Default for GenericConfig<E> {
     fn default() -> Self {
         GenericConfig {
             default_sensitivity: default_sensitivity(),
+            desktop: <_>::default(),
+            unmatched: <_>::default(),
+            default_on_enter: <_>::default(),
+            default_on_exit: <_>::default(),
+            script: <_>::default(),
+            script_timeout_ms: default_script_timeout_ms(),
+            idle_timeout_secs: <_>::default(),
+            command_timeout_secs: default_command_timeout_secs(),
             processes: <_>::default(),
+            logs: <_>::default(),
+            log_level: <_>::default(),
+            http: <_>::default(),
+            hotkeys: <_>::default(),
         }
     }
 }
@@ -34,11 +96,27 @@ pub const fn default_sensitivity() -> f64 {
     1.0
 }
 
+const fn default_script_timeout_ms() -> u64 {
+    20
+}
+
+fn is_default_script_timeout(ms: &u64) -> bool {
+    *ms == default_script_timeout_ms()
+}
+
+const fn default_command_timeout_secs() -> u64 {
+    30
+}
+
+fn is_default_command_timeout(secs: &u64) -> bool {
+    *secs == default_command_timeout_secs()
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 #[serde(untagged)]
 pub enum ConfigEntry {
     Short(f64),
-    Long(Entry),
+    Long(#[serde(with = "Entry")] Entry),
 }
 
 impl From<ConfigEntry> for Entry {
@@ -47,23 +125,147 @@ impl From<ConfigEntry> for Entry {
             ConfigEntry::Long(entry) => entry,
             ConfigEntry::Short(sensitivity) => Entry {
                 sensitivity,
-                only_if_cursor_hidden: <_>::default(),
+                ..<_>::default()
             },
         }
     }
 }
 
-#[derive(Debug, Deserialize)]
-#[serde(from = "ConfigEntry")]
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(remote = "Self")]
 pub struct Entry {
     pub sensitivity: f64,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "is_default")]
     pub only_if_cursor_hidden: bool,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub activation: Activation,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub priority: i32,
+    /// run when the entry starts being applied
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub on_enter: Option<String>,
+    /// run when the entry stops being applied
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub on_exit: Option<String>,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub while_held: Option<WhileHeld>,
+}
+
+/// a sensitivity that's applied instead of the entry's while an input is held
+/// in its process
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct WhileHeld {
+    #[serde(flatten)]
+    pub input: Input,
+    pub sensitivity: f64,
+}
+
+/// given as `button: right` or `key: shift`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Input {
+    Button(Button),
+    /// the name of a key, like in hotkeys, or one of the modifiers
+    Key(String),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Button {
+    Left,
+    Right,
+    Middle,
+    X1,
+    X2,
+}
+
+/// when an entry's sensitivity should be applied
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Activation {
+    /// while the process has focus
+    #[default]
+    Focused,
+    /// while the process is running, regardless of focus
+    Running,
+}
+
+/// what a hotkey does
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum Action {
+    /// changes the sensitivity of the applied entry by `step`, optionally
+    /// saving it to the config file
+    Nudge {
+        step: f64,
+        #[serde(default, skip_serializing_if = "is_default")]
+        persist: bool,
+    },
+    TogglePause,
+}
+
+/// when the output and log files of the running instance are rotated. only
+/// read when senscale starts.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Logs {
+    pub max_size_kb: u64,
+    pub max_age_days: u64,
+    /// how many rotated files are kept in addition to the current one
+    pub keep: usize,
+}
+
+impl Default for Logs {
+    fn default() -> Self {
+        Logs {
+            max_size_kb: 1024,
+            max_age_days: 7,
+            keep: 3,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Http {
+    #[serde(default = "default_http_port")]
+    pub port: u16,
+}
+
+const fn default_http_port() -> u16 {
+    47474
+}
+
+impl Logs {
+    pub fn rotation(&self) -> Rotation {
+        Rotation {
+            max_size: self.max_size_kb * 1024,
+            max_age: Duration::from_secs(self.max_age_days * 24 * 60 * 60),
+            keep: self.keep,
+        }
+    }
+}
+
+fn is_default<T: Default + PartialEq>(value: &T) -> bool {
+    *value == T::default()
 }
 
 impl Entry {
     fn can_be_short(&self) -> bool {
-        self.only_if_cursor_hidden == <_>::default()
+        is_default(&self.only_if_cursor_hidden)
+            && is_default(&self.activation)
+            && is_default(&self.priority)
+            && is_default(&self.on_enter)
+            && is_default(&self.on_exit)
+            && is_default(&self.while_held)
+    }
+}
+
+impl<'de> Deserialize<'de> for Entry {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        ConfigEntry::deserialize(deserializer).map(<_>::from)
     }
 }
 
@@ -75,18 +277,7 @@ impl Serialize for Entry {
         if self.can_be_short() {
             ConfigEntry::Short(self.sensitivity).serialize(serializer)
         } else {
-            #[derive(Serialize)]
-            struct EntryProxy {
-                sensitivity: f64,
-                only_if_cursor_hidden: bool,
-            }
-
-            let proxy = EntryProxy {
-                sensitivity: self.sensitivity,
-                only_if_cursor_hidden: self.only_if_cursor_hidden,
-            };
-
-            proxy.serialize(serializer)
+            Entry::serialize(self, serializer)
         }
     }
 }
@@ -96,6 +287,11 @@ impl Default for Entry {
         Self {
             sensitivity: default_sensitivity(),
             only_if_cursor_hidden: <_>::default(),
+            activation: <_>::default(),
+            priority: <_>::default(),
+            on_enter: <_>::default(),
+            on_exit: <_>::default(),
+            while_held: <_>::default(),
         }
     }
 }
@@ -104,14 +300,30 @@ pub fn read_config() -> Result<Config> {
     let file = config_dir()?.file();
 
     if file.exists() {
-        let yaml = File::open(file)?;
-
-        serde_yaml::from_reader(BufReader::new(yaml)).map_err(<_>::into)
+        parse_config(file)
     } else {
         create_config(file)
     }
 }
 
+/// like [`read_config`], but returns the config a missing file would be
+/// created with instead of creating it
+pub fn read_existing_config() -> Result<Config> {
+    let file = config_dir()?.file();
+
+    if file.exists() {
+        parse_config(file)
+    } else {
+        Ok(Config::default())
+    }
+}
+
+fn parse_config(file: impl AsRef<Path>) -> Result<Config> {
+    let yaml = File::open(file)?;
+
+    serde_yaml::from_reader(BufReader::new(yaml)).map_err(<_>::into)
+}
+
 pub fn create_config(file: impl AsRef<Path>) -> Result<Config> {
     if let Some(dir) = file.as_ref().parent() {
         fs::create_dir_all(dir)?
@@ -132,6 +344,21 @@ pub fn write_config<E: Serialize>(config: &GenericConfig<E>, mut writer: impl Wr
     Ok(())
 }
 
+/// sets the sensitivity of the entry for `process` in the config file at
+/// `path`, adding the entry if there is none
+pub fn set_sensitivity(path: impl AsRef<Path>, process: PathBuf, sensitivity: f64) -> Result {
+    let mut config: EditableConfig = serde_yaml::from_reader(BufReader::new(File::open(&path)?))?;
+
+    config.processes.entry(process).or_default().sensitivity = sensitivity;
+
+    let file = fs::OpenOptions::new()
+        .write(true)
+        .truncate(true)
+        .open(path)?;
+
+    write_config(&config, BufWriter::new(file))
+}
+
 pub fn config_dir() -> Result<ConfigDir> {
     let dirs = ProjectDirs::from("io.github", "reslario", "senscale")
         .ok_or("couldn't get program directories")?;
@@ -156,3 +383,89 @@ impl ConfigDir {
         self.join("config.yaml")
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const YAML: &str = "\
+default_sensitivity: 1.0
+desktop: 0.8
+unmatched: 1.2
+script: senscale.rhai
+script_timeout_ms: 50
+idle_timeout_secs: 300
+processes:
+  short.exe: 2.0
+  long.exe:
+    sensitivity: 3.0
+    only_if_cursor_hidden: true
+  running.exe:
+    sensitivity: 4.0
+    activation: running
+    priority: 2
+    on_enter: rgb --profile paint
+  shooter.exe:
+    sensitivity: 1.5
+    while_held:
+      button: right
+      sensitivity: 0.6
+logs:
+  max_size_kb: 256
+  max_age_days: 7
+  keep: 1
+log_level: debug
+http:
+  port: 8080
+hotkeys:
+  Ctrl+Alt+Up:
+    action: nudge
+    step: 0.1
+    persist: true
+  Ctrl+Alt+P:
+    action: toggle_pause
+";
+
+    #[test]
+    fn read_entries() {
+        let config: Config = serde_yaml::from_str(YAML).unwrap();
+        let entry = |exe: &str| &config.processes[Path::new(exe)];
+
+        assert_eq!(entry("short.exe").sensitivity, 2.);
+        assert!(entry("long.exe").only_if_cursor_hidden);
+        assert_eq!(entry("running.exe").activation, Activation::Running);
+        assert_eq!(entry("running.exe").priority, 2);
+        assert_eq!(
+            entry("running.exe").on_enter.as_deref(),
+            Some("rgb --profile paint")
+        );
+        assert_eq!(config.script.as_deref(), Some(Path::new("senscale.rhai")));
+        assert_eq!(config.desktop, Some(0.8));
+        assert_eq!(config.unmatched, Some(1.2));
+        assert_eq!(config.script_timeout_ms, 50);
+        assert_eq!(config.idle_timeout_secs, Some(300));
+        assert_eq!(
+            entry("shooter.exe").while_held,
+            Some(WhileHeld {
+                input: Input::Button(Button::Right),
+                sensitivity: 0.6
+            })
+        );
+        assert_eq!(config.logs.rotation().max_size, 256 * 1024);
+        assert_eq!(config.logs.keep, 1);
+        assert_eq!(config.log_level, Level::Debug);
+        assert_eq!(config.http, Some(Http { port: 8080 }));
+        assert_eq!(config.hotkeys["Ctrl+Alt+Up"], Action::Nudge {
+            step: 0.1,
+            persist: true
+        });
+        assert_eq!(config.hotkeys["Ctrl+Alt+P"], Action::TogglePause)
+    }
+
+    #[test]
+    fn entries_roundtrip() {
+        let config: EditableConfig = serde_yaml::from_str(YAML).unwrap();
+
+        assert_eq!(serde_yaml::to_string(&config).unwrap(), YAML)
+    }
+}
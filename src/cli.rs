@@ -1,10 +1,20 @@
-use std::str::FromStr;
-
-use {crate::thread_id::ThreadId, argh::FromArgs, std::path::PathBuf};
+use {
+    crate::{log::Level, msg},
+    argh::FromArgs,
+    std::{path::PathBuf, time::Duration},
+};
 
 /// scales your mouse sensitivity on a per-process basis.
 #[derive(FromArgs)]
 pub struct Args {
+    /// how long to wait for a running instance to respond, e.g. 500ms or 10s
+    /// (5s by default)
+    #[argh(
+        option,
+        default = "Duration::from_secs(5)",
+        from_str_fn(parse_duration)
+    )]
+    pub timeout: Duration,
     #[argh(subcommand)]
     pub command: Command,
 }
@@ -19,6 +29,14 @@ pub enum Command {
     PrintOutput(PrintOutput),
     Adjust(Adjust),
     Config(Config),
+    Explain(Explain),
+    Status(Status),
+    Set(Set),
+    Pause(Pause),
+    Resume(Resume),
+    Ping(Ping),
+    Logs(Logs),
+    Watch(Watch),
 }
 
 /// runs senscale
@@ -28,10 +46,13 @@ pub struct Run {
     /// runs senscale in the foreground
     #[argh(switch)]
     pub foreground: bool,
-    /// sets the thread used for message passing
-    /// (used internally when running in the background)
+    /// also outputs debug messages, like why each sensitivity was applied,
+    /// regardless of the configured log level
+    #[argh(switch)]
+    pub verbose: bool,
+    /// writes output to a file that's rotated as it grows instead of stderr
     #[argh(option)]
-    pub parent_thread: Option<ThreadId>,
+    pub output: Option<PathBuf>,
 }
 
 /// stops senscale
@@ -50,7 +71,8 @@ pub struct Reload {}
 pub struct Clean {}
 
 /// prints all the output from a running instance that
-/// has accumulated since the last time it was checked
+/// has accumulated since the last time it was checked,
+/// except output that was already shown by other commands
 #[derive(FromArgs)]
 #[argh(subcommand, name = "print-output")]
 pub struct PrintOutput {}
@@ -70,10 +92,177 @@ pub struct Adjust {
 #[argh(subcommand, name = "config")]
 pub struct Config {}
 
-impl FromStr for ThreadId {
-    type Err = <u32 as FromStr>::Err;
+/// shows which config entries would apply to a process and why, without
+/// affecting a running instance
+#[derive(FromArgs)]
+#[argh(subcommand, name = "explain")]
+pub struct Explain {
+    /// the .exe of the process
+    #[argh(positional)]
+    pub process: PathBuf,
+    /// simulates the cursor being hidden
+    #[argh(switch)]
+    pub cursor_hidden: bool,
+    /// simulates the process running in the background instead of being
+    /// focused
+    #[argh(switch)]
+    pub background: bool,
+    /// simulates another process running at the same time (can be repeated)
+    #[argh(option)]
+    pub running: Vec<PathBuf>,
+}
+
+/// shows what a running instance is currently doing
+#[derive(FromArgs)]
+#[argh(subcommand, name = "status")]
+pub struct Status {
+    /// prints the status as JSON
+    #[argh(switch)]
+    pub json: bool,
+}
+
+/// temporarily overrides the sensitivity of a running instance, taking
+/// precedence over the config
+#[derive(FromArgs)]
+#[argh(subcommand, name = "set")]
+pub struct Set {
+    /// the sensitivity to use
+    #[argh(positional, from_str_fn(parse_sensitivity))]
+    pub sensitivity: Option<f64>,
+    /// how long the override lasts, e.g. 30s, 10m or 1h30m (until cleared if
+    /// not specified)
+    #[argh(option, long = "for", from_str_fn(parse_duration))]
+    pub duration: Option<Duration>,
+    /// limits the override to a process, given as a full path or file name
+    #[argh(option)]
+    pub process: Option<PathBuf>,
+    /// removes the current override
+    #[argh(switch)]
+    pub clear: bool,
+}
+
+/// applies the default sensitivity and stops scaling until resumed, without
+/// stopping senscale
+#[derive(FromArgs)]
+#[argh(subcommand, name = "pause")]
+pub struct Pause {}
+
+/// resumes scaling after it was paused
+#[derive(FromArgs)]
+#[argh(subcommand, name = "resume")]
+pub struct Resume {}
+
+/// checks whether a running instance responds
+#[derive(FromArgs)]
+#[argh(subcommand, name = "ping")]
+pub struct Ping {}
+
+/// shows the log of running and previous instances
+#[derive(FromArgs)]
+#[argh(subcommand, name = "logs")]
+pub struct Logs {
+    /// keeps printing new entries as they're logged
+    #[argh(switch, short = 'f')]
+    pub follow: bool,
+    /// only shows entries from the given period, e.g. 10m or 1h
+    #[argh(option, from_str_fn(parse_duration))]
+    pub since: Option<Duration>,
+    /// only shows entries at or above a level: debug, info (the default), warn
+    /// or error
+    #[argh(option, default = "Level::Info")]
+    pub level: Level,
+    /// prints entries as JSON, one per line
+    #[argh(switch)]
+    pub json: bool,
+}
+
+/// prints events from the running instance as JSON, one per line, until
+/// stopped
+#[derive(FromArgs)]
+#[argh(subcommand, name = "watch")]
+pub struct Watch {}
+
+fn parse_sensitivity(value: &str) -> Result<f64, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid sensitivity {value}"))
+        .and_then(msg::valid_sensitivity)
+}
+
+/// parses durations like 500ms, 90s, 10m or 1h30m
+fn parse_duration(value: &str) -> Result<Duration, String> {
+    let invalid = || format!("invalid duration: {value}");
+    let mut millis = 0u64;
+    let mut rest = value;
+
+    while !rest.is_empty() {
+        let digits = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let amount = rest[..digits].parse::<u64>().map_err(|_| invalid())?;
+        rest = &rest[digits..];
+
+        let letters = rest
+            .find(|c: char| !c.is_ascii_alphabetic())
+            .unwrap_or(rest.len());
+
+        let unit = match &rest[..letters] {
+            "h" => 3_600_000,
+            "m" => 60_000,
+            "s" => 1000,
+            "ms" => 1,
+            _ => return Err(invalid()),
+        };
+
+        millis = amount
+            .checked_mul(unit)
+            .and_then(|amount| millis.checked_add(amount))
+            .ok_or_else(invalid)?;
+        rest = &rest[letters..];
+    }
+
+    if value.is_empty() {
+        return Err(invalid())
+    }
+
+    Ok(Duration::from_millis(millis))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn sensitivities() {
+        assert_eq!(parse_sensitivity("1.5"), Ok(1.5));
+
+        for invalid in ["", "x", "0", "-1", "NaN", "inf"] {
+            assert!(parse_sensitivity(invalid).is_err(), "{invalid}")
+        }
+    }
+
+    #[test]
+    fn durations() {
+        let secs = |value| parse_duration(value).map(|duration| duration.as_secs());
+
+        assert_eq!(parse_duration("1s500ms"), Ok(Duration::from_millis(1500)));
+        assert_eq!(secs("90s"), Ok(90));
+        assert_eq!(secs("10m"), Ok(600));
+        assert_eq!(secs("1h30m"), Ok(5400));
+        assert_eq!(secs("1h1m1s"), Ok(3661));
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        s.parse().map(u32::into)
+        for invalid in [
+            "",
+            "10",
+            "m",
+            "10x",
+            "10sm",
+            "1.5h",
+            "-1m",
+            "9999999999999999h",
+            "18446744073709551615ms1ms",
+        ] {
+            assert!(secs(invalid).is_err(), "{invalid}")
+        }
     }
 }
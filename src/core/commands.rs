@@ -0,0 +1,386 @@
+//! commands configured to run when entries start or stop being applied
+
+use {
+    crate::{
+        output::report,
+        windows::{handle::Handle, util::validate},
+    },
+    std::{
+        io::{self, BufRead, BufReader, Read},
+        os::windows::{io::AsRawHandle, process::CommandExt},
+        path::PathBuf,
+        process::{Child, Command, Stdio},
+        ptr,
+        sync::{
+            mpsc::{self, Sender},
+            Mutex,
+            OnceLock,
+        },
+        thread,
+        time::{Duration, Instant},
+    },
+    winapi::um::{
+        jobapi2::{AssignProcessToJobObject, CreateJobObjectW, TerminateJobObject},
+        winbase::CREATE_NO_WINDOW,
+    },
+};
+
+const WAIT_INTERVAL: Duration = Duration::from_millis(50);
+
+/// where the applied sensitivity comes from
+#[derive(Debug, Clone, PartialEq)]
+pub enum Source {
+    Default,
+    /// the entry with the given key
+    Entry(PathBuf),
+    /// overrides and scripts don't have commands
+    Override,
+    Script,
+}
+
+/// a source that's about to be applied, along with its commands
+pub struct Applied {
+    pub source: Source,
+    /// the process the source is applied for, if any
+    pub process: Option<PathBuf>,
+    pub sensitivity: f64,
+    pub on_enter: Option<String>,
+    pub on_exit: Option<String>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Event {
+    Enter,
+    Exit,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Invocation {
+    pub command: String,
+    pub event: Event,
+    pub source: Source,
+    pub process: Option<PathBuf>,
+    pub sensitivity: f64,
+}
+
+/// keeps track of the applied source to determine which commands to run
+pub struct Transitions {
+    current: Option<Applied>,
+}
+
+impl Transitions {
+    pub const fn new() -> Transitions {
+        Transitions { current: None }
+    }
+
+    /// whether switching to `next` would change the applied source or
+    /// sensitivity
+    pub fn changes(&self, next: &Applied) -> bool {
+        self.current.as_ref().is_none_or(|current| {
+            current.source != next.source || current.sensitivity != next.sensitivity
+        })
+    }
+
+    /// returns the commands that should be run when switching to `next`,
+    /// which are none if its source is already applied
+    pub fn switch(&mut self, next: Applied) -> Vec<Invocation> {
+        if let Some(current) = self
+            .current
+            .as_mut()
+            .filter(|current| current.source == next.source)
+        {
+            current.sensitivity = next.sensitivity;
+            return Vec::new()
+        }
+
+        let exit = self.current.take().and_then(|current| {
+            Some(Invocation {
+                command: current.on_exit?,
+                event: Event::Exit,
+                source: current.source,
+                process: current.process,
+                sensitivity: current.sensitivity,
+            })
+        });
+
+        let enter = next.on_enter.clone().map(|command| Invocation {
+            command,
+            event: Event::Enter,
+            source: next.source.clone(),
+            process: next.process.clone(),
+            sensitivity: next.sensitivity,
+        });
+
+        self.current = Some(next);
+
+        exit.into_iter().chain(enter).collect()
+    }
+}
+
+static TRANSITIONS: Mutex<Transitions> = Mutex::new(Transitions::new());
+
+/// whether switching to `next` would change what's applied, see
+/// [`Transitions::changes`]
+pub fn changes(next: &Applied) -> bool {
+    TRANSITIONS.lock().unwrap().changes(next)
+}
+
+type Batch = (Vec<Invocation>, Duration);
+
+static BATCHES: OnceLock<Sender<Batch>> = OnceLock::new();
+
+/// runs the commands for switching to `next` in the background, one after
+/// another and after the ones for previous switches
+pub fn switch(next: Applied, timeout: Duration) {
+    let mut transitions = TRANSITIONS.lock().unwrap();
+    let invocations = transitions.switch(next);
+
+    // sent while locked, so the batches are queued in the order of the switches
+    if !invocations.is_empty() {
+        let _ = BATCHES
+            .get_or_init(run_batches)
+            .send((invocations, timeout));
+    }
+}
+
+/// runs batches on a single thread, so an on_exit command can't run at the
+/// same time as or after the next on_enter command
+fn run_batches() -> Sender<Batch> {
+    let (sender, receiver) = mpsc::channel::<Batch>();
+
+    thread::spawn(move || {
+        for (invocations, timeout) in receiver {
+            for invocation in invocations {
+                run(&invocation, timeout)
+            }
+        }
+    });
+
+    sender
+}
+
+fn run(invocation: &Invocation, timeout: Duration) {
+    let name = match invocation.event {
+        Event::Enter => "on_enter",
+        Event::Exit => "on_exit",
+    };
+
+    report!(
+        Debug,
+        Command,
+        "running {name} command: {}",
+        invocation.command
+    );
+
+    match execute(invocation, timeout) {
+        Ok(Some(0)) => {}
+        Ok(Some(code)) => report!(Warn, Command, "{name} command exited with code {code}"),
+        Ok(None) => report!(
+            Warn,
+            Command,
+            "{name} command didn't finish within {}s and was killed",
+            timeout.as_secs()
+        ),
+        Err(e) => report!(Warn, Command, "couldn't run {name} command: {e}"),
+    }
+}
+
+/// returns the exit code, or `None` if the command timed out
+fn execute(invocation: &Invocation, timeout: Duration) -> io::Result<Option<i32>> {
+    let entry = match &invocation.source {
+        Source::Entry(key) => key.clone(),
+        _ => PathBuf::new(),
+    };
+
+    // the command is passed as is, so it's interpreted the same way as when
+    // it's typed into cmd
+    let mut child = Command::new("cmd")
+        .arg("/C")
+        .raw_arg(&invocation.command)
+        .env("SENSCALE_EVENT", match invocation.event {
+            Event::Enter => "enter",
+            Event::Exit => "exit",
+        })
+        .env(
+            "SENSCALE_PROCESS",
+            invocation.process.clone().unwrap_or_default(),
+        )
+        .env("SENSCALE_ENTRY", entry)
+        .env("SENSCALE_SENSITIVITY", invocation.sensitivity.to_string())
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .creation_flags(CREATE_NO_WINDOW)
+        .spawn()?;
+
+    let job = match Job::assign(&child) {
+        Ok(job) => Some(job),
+        Err(e) => {
+            report!(Debug, Command, "couldn't assign command to a job: {e}");
+            None
+        }
+    };
+
+    let readers = [
+        child.stdout.take().map(log_lines),
+        child.stderr.take().map(log_lines),
+    ];
+
+    let code = wait(&mut child, job.as_ref(), timeout)?;
+
+    // processes started by a command that timed out may still hold on to its
+    // output, so the rest of it is logged whenever it arrives
+    if code.is_some() {
+        for reader in readers.into_iter().flatten() {
+            let _ = reader.join();
+        }
+    }
+
+    Ok(code)
+}
+
+/// writes the output of a command to the log as it's produced
+fn log_lines(output: impl Read + Send + 'static) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        for line in BufReader::new(output).lines().map_while(io::Result::ok) {
+            report!(Debug, Command, "> {line}")
+        }
+    })
+}
+
+/// a job object a command is assigned to, so that the processes it starts
+/// can be killed along with it. they're left running once the job is dropped.
+struct Job(Handle);
+
+impl Job {
+    fn assign(child: &Child) -> io::Result<Job> {
+        let job = Job(Handle::new(unsafe {
+            CreateJobObjectW(ptr::null_mut(), ptr::null())
+        })?);
+
+        validate(unsafe { AssignProcessToJobObject(job.0.raw(), child.as_raw_handle() as _) })?;
+
+        Ok(job)
+    }
+
+    fn kill(&self) -> io::Result<()> {
+        validate(unsafe { TerminateJobObject(self.0.raw(), 1) })
+    }
+}
+
+/// waits for the command to exit, killing it and everything it started once
+/// the timeout passes
+fn wait(child: &mut Child, job: Option<&Job>, timeout: Duration) -> io::Result<Option<i32>> {
+    let deadline = Instant::now() + timeout;
+
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status.code().unwrap_or(-1)))
+        }
+
+        if Instant::now() >= deadline {
+            match job {
+                Some(job) => job.kill()?,
+                None => child.kill()?,
+            }
+
+            child.wait()?;
+            return Ok(None)
+        }
+
+        thread::sleep(WAIT_INTERVAL)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn applied(source: Source, on_enter: Option<&str>, on_exit: Option<&str>) -> Applied {
+        Applied {
+            source,
+            process: Some("game.exe".into()),
+            sensitivity: 2.,
+            on_enter: on_enter.map(<_>::into),
+            on_exit: on_exit.map(<_>::into),
+        }
+    }
+
+    fn commands(invocations: Vec<Invocation>) -> Vec<(String, Event)> {
+        invocations
+            .into_iter()
+            .map(|invocation| (invocation.command, invocation.event))
+            .collect()
+    }
+
+    #[test]
+    fn transitions() {
+        let mut transitions = Transitions::new();
+        let game = || Source::Entry("game.exe".into());
+
+        assert_eq!(
+            commands(transitions.switch(applied(Source::Default, Some("default"), None))),
+            [("default".into(), Event::Enter)]
+        );
+        assert_eq!(
+            commands(transitions.switch(applied(game(), Some("enter"), Some("exit")))),
+            [("enter".into(), Event::Enter)]
+        );
+
+        // nothing changed
+        assert!(!transitions.changes(&applied(game(), Some("enter"), Some("exit"))));
+        assert!(transitions
+            .switch(applied(game(), Some("enter"), Some("exit")))
+            .is_empty());
+
+        // only the sensitivity changed, which doesn't run any commands
+        let faster = Applied {
+            sensitivity: 3.,
+            ..applied(game(), Some("enter"), Some("exit"))
+        };
+        assert!(transitions.changes(&faster));
+        assert!(transitions.switch(faster).is_empty());
+        assert!(!transitions.changes(&Applied {
+            sensitivity: 3.,
+            ..applied(game(), None, None)
+        }));
+
+        let invocations = transitions.switch(applied(Source::Override, None, None));
+        assert_eq!(invocations, [Invocation {
+            command: "exit".into(),
+            event: Event::Exit,
+            source: game(),
+            process: Some("game.exe".into()),
+            sensitivity: 3.,
+        }]);
+    }
+
+    #[test]
+    fn execution() {
+        let invocation = |command: &str| Invocation {
+            command: command.into(),
+            event: Event::Enter,
+            source: Source::Entry("game.exe".into()),
+            process: Some(r"C:\game.exe".into()),
+            sensitivity: 2.5,
+        };
+        let timeout = Duration::from_secs(10);
+
+        assert_eq!(
+            execute(
+                &invocation(r#"if "%SENSCALE_SENSITIVITY%"=="2.5" exit 3"#),
+                timeout
+            )
+            .unwrap(),
+            Some(3)
+        );
+        assert_eq!(
+            execute(
+                &invocation("ping -n 10 127.0.0.1"),
+                Duration::from_millis(100)
+            )
+            .unwrap(),
+            None
+        )
+    }
+}
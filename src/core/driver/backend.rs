@@ -0,0 +1,61 @@
+//! the connection settings are written through
+
+use {
+    super::{settings::Settings, write},
+    std::{fs::File, io, path::PathBuf},
+};
+
+pub const RAWACCEL_DEVICE: &str = r"\\.\rawaccel";
+
+pub trait Backend: Send {
+    /// opens a new connection, replacing the previous one
+    fn connect(&mut self) -> io::Result<()>;
+    fn write(&mut self, settings: &mut Settings) -> io::Result<()>;
+    fn read(&mut self) -> io::Result<Settings>;
+}
+
+/// the device RawAccel's driver exposes
+pub struct RawAccel {
+    path: PathBuf,
+    handle: Option<File>,
+}
+
+impl RawAccel {
+    pub fn new(path: impl Into<PathBuf>) -> RawAccel {
+        RawAccel {
+            path: path.into(),
+            handle: None,
+        }
+    }
+
+    fn handle(&self) -> io::Result<&File> {
+        self.handle
+            .as_ref()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotConnected, "not connected"))
+    }
+}
+
+impl Backend for RawAccel {
+    fn connect(&mut self) -> io::Result<()> {
+        // the old handle is closed first, in case the driver only allows one
+        self.handle = None;
+        self.handle = Some(File::open(&self.path).map_err(rawaccel_file_error)?);
+        Ok(())
+    }
+
+    fn write(&mut self, settings: &mut Settings) -> io::Result<()> {
+        write::write_settings(self.handle()?, settings)
+    }
+
+    fn read(&mut self) -> io::Result<Settings> {
+        write::read_settings(self.handle()?)
+    }
+}
+
+fn rawaccel_file_error(e: io::Error) -> io::Error {
+    if e.kind() == io::ErrorKind::NotFound {
+        io::Error::new(e.kind(), "RawAccel driver not installed")
+    } else {
+        e
+    }
+}
@@ -0,0 +1,59 @@
+//! how long to wait between attempts to reconnect to the driver
+
+use std::time::{Duration, Instant};
+
+const INITIAL_DELAY: Duration = Duration::from_millis(500);
+const MAX_DELAY: Duration = Duration::from_secs(30);
+
+/// the first attempt is due right away, and each failed one doubles the delay
+/// until the next, up to a limit
+#[derive(Debug, Default)]
+pub struct Backoff {
+    delay: Duration,
+    next: Option<Instant>,
+}
+
+impl Backoff {
+    pub fn due(&self, now: Instant) -> bool {
+        self.next.is_none_or(|next| now >= next)
+    }
+
+    pub fn failed(&mut self, now: Instant) {
+        self.delay = if self.delay.is_zero() {
+            INITIAL_DELAY
+        } else {
+            (self.delay * 2).min(MAX_DELAY)
+        };
+
+        self.next = Some(now + self.delay)
+    }
+
+    pub fn delay(&self) -> Duration {
+        self.delay
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn doubles_up_to_the_limit() {
+        let now = Instant::now();
+        let mut backoff = Backoff::default();
+        assert!(backoff.due(now));
+
+        backoff.failed(now);
+        assert!(!backoff.due(now + Duration::from_millis(499)));
+        assert!(backoff.due(now + INITIAL_DELAY));
+
+        let delays = (0..8)
+            .map(|_| {
+                backoff.failed(now);
+                backoff.delay().as_secs()
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(delays, [1, 2, 4, 8, 16, 30, 30, 30])
+    }
+}
@@ -1,56 +1,288 @@
+mod backend;
+mod backoff;
 mod settings;
 mod write;
 
 use {
+    crate::output::report,
+    backend::{Backend, RawAccel, RAWACCEL_DEVICE},
+    backoff::Backoff,
     settings::Settings,
-    std::{fs::File, io},
+    std::{io, time::Instant},
+    winapi::shared::winerror::{
+        ERROR_DEVICE_NOT_CONNECTED,
+        ERROR_DEVICE_REMOVED,
+        ERROR_DEV_NOT_EXIST,
+        ERROR_FILE_NOT_FOUND,
+        ERROR_INVALID_HANDLE,
+        ERROR_NOT_READY,
+        ERROR_OPERATION_ABORTED,
+    },
 };
 
 pub struct Driver {
     sensitivity: f64,
-    handle: File,
+    backend: Box<dyn Backend>,
+    /// when to try reconnecting, while the connection is broken
+    backoff: Option<Backoff>,
+    /// the error that occurred during the last write, if it failed
+    error: Option<String>,
 }
 
 impl Driver {
     pub fn new() -> io::Result<Driver> {
-        File::open(r"\\.\rawaccel")
-            .map_err(rawaccel_file_error)
-            .map(|handle| Driver {
-                sensitivity: f64::NAN,
-                handle,
-            })
+        Driver::connect(RawAccel::new(RAWACCEL_DEVICE))
     }
 
-    pub fn set_sens(&mut self, sens: f64) -> io::Result<()> {
+    fn connect(mut backend: impl Backend + 'static) -> io::Result<Driver> {
+        backend.connect()?;
+
+        Ok(Driver {
+            sensitivity: f64::NAN,
+            backend: Box::new(backend),
+            backoff: None,
+            error: None,
+        })
+    }
+
+    /// returns whether the settings were written, which they aren't if the
+    /// sensitivity didn't change
+    pub fn set_sens(&mut self, sens: f64) -> io::Result<bool> {
+        self.set_sens_at(sens, Instant::now())
+    }
+
+    fn set_sens_at(&mut self, sens: f64, now: Instant) -> io::Result<bool> {
         // this is only meant to prevent setting the sensitivity
         // to the exact same value consecutively, so an exact
         // comparison is fine here
         #[allow(clippy::float_cmp)]
-        if sens != self.sensitivity {
-            self.sensitivity = sens;
+        if sens == self.sensitivity {
+            return Ok(false)
+        }
+
+        self.sensitivity = sens;
+
+        let res = self.apply(now);
+        self.error = res.as_ref().err().map(<_>::to_string);
+        res.map(|_| true)
+    }
+
+    /// writes the sensitivity, reconnecting first if the connection broke
+    fn apply(&mut self, now: Instant) -> io::Result<()> {
+        match &self.backoff {
+            None => match self.write() {
+                Err(e) if disconnected(&e) => {
+                    report!(Warn, Driver, "lost the connection to the driver: {e}");
+                    self.backoff = Some(<_>::default());
+                    self.reconnect(now)
+                }
+                res => res,
+            },
+            Some(backoff) if backoff.due(now) => self.reconnect(now),
+            Some(_) => Err(io::Error::new(
+                io::ErrorKind::NotConnected,
+                "not connected to the driver, waiting to reconnect",
+            )),
+        }
+    }
+
+    /// tries to reconnect if the connection broke and another attempt is due,
+    /// writing the last sensitivity again once it worked
+    pub fn poll(&mut self, now: Instant) {
+        if self
+            .backoff
+            .as_ref()
+            .is_some_and(|backoff| backoff.due(now))
+        {
+            self.error = self.reconnect(now).err().map(|e| e.to_string())
+        }
+    }
+
+    fn reconnect(&mut self, now: Instant) -> io::Result<()> {
+        let res = self.backend.connect().and_then(|_| self.write());
+
+        match &res {
+            Ok(()) => {
+                report!(Info, Driver, "reconnected to the driver");
+                self.backoff = None
+            }
+            Err(e) => {
+                if let Some(backoff) = &mut self.backoff {
+                    backoff.failed(now);
+                    report!(
+                        Debug,
+                        Driver,
+                        "couldn't reconnect to the driver, retrying in {:?}: {e}",
+                        backoff.delay()
+                    )
+                }
+            }
+        }
+
+        res
+    }
+
+    fn write(&mut self) -> io::Result<()> {
+        // nothing was applied yet
+        let Some(sens) = self.sensitivity() else {
+            return Ok(())
+        };
+
+        let mut settings = Settings::default();
+        settings.set_sens(sens);
 
-            let mut settings = Settings::default();
-            settings.set_sens(sens);
+        self.backend.write(&mut settings)
+    }
+
+    /// reads the settings back from the driver, which may have been reset
+    /// since they were last written, e.g. while the system was asleep. if they
+    /// differ or can't be read, the next sensitivity is written regardless.
+    pub fn refresh(&mut self) -> io::Result<()> {
+        let res = self.backend.read();
 
-            write::write_settings(&self.handle, &mut settings)
-        } else {
-            Ok(())
+        #[allow(clippy::float_cmp)]
+        if !res
+            .as_ref()
+            .is_ok_and(|settings| settings.sens() == self.sensitivity)
+        {
+            self.sensitivity = f64::NAN
         }
+
+        res.map(drop)
+    }
+
+    /// the sensitivity that was last written, if any
+    pub fn sensitivity(&self) -> Option<f64> {
+        (!self.sensitivity.is_nan()).then_some(self.sensitivity)
+    }
+
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
     }
 
     #[cfg(test)]
     pub fn dummy() -> io::Result<Driver> {
-        File::open(std::env::current_exe()?).map(|handle| Driver {
-            sensitivity: f64::NAN,
-            handle,
-        })
+        Driver::connect(RawAccel::new(std::env::current_exe()?))
     }
 }
 
-fn rawaccel_file_error(e: io::Error) -> io::Error {
-    if e.kind() == io::ErrorKind::NotFound {
-        io::Error::new(e.kind(), "RawAccel driver not installed")
-    } else {
-        e
+/// whether the error means the handle doesn't refer to a running driver anymore
+fn disconnected(e: &io::Error) -> bool {
+    e.kind() == io::ErrorKind::NotConnected
+        || e.raw_os_error().is_some_and(|code| {
+            matches!(
+                code as u32,
+                ERROR_INVALID_HANDLE
+                    | ERROR_FILE_NOT_FOUND
+                    | ERROR_DEV_NOT_EXIST
+                    | ERROR_DEVICE_NOT_CONNECTED
+                    | ERROR_DEVICE_REMOVED
+                    | ERROR_OPERATION_ABORTED
+                    | ERROR_NOT_READY
+            )
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        std::{
+            sync::{Arc, Mutex},
+            time::Duration,
+        },
+    };
+
+    #[derive(Default)]
+    struct Device {
+        /// whether the driver is running, so connecting works
+        running: bool,
+        /// whether the current connection works
+        connected: bool,
+        connects: u32,
+        written: Vec<f64>,
+    }
+
+    #[derive(Clone, Default)]
+    struct FakeBackend(Arc<Mutex<Device>>);
+
+    impl FakeBackend {
+        fn device(&self) -> std::sync::MutexGuard<'_, Device> {
+            self.0.lock().unwrap()
+        }
+    }
+
+    impl Backend for FakeBackend {
+        fn connect(&mut self) -> io::Result<()> {
+            let mut device = self.device();
+            device.connects += 1;
+            device.connected = device.running;
+
+            if device.running {
+                Ok(())
+            } else {
+                Err(io::ErrorKind::NotFound.into())
+            }
+        }
+
+        fn write(&mut self, settings: &mut Settings) -> io::Result<()> {
+            let mut device = self.device();
+
+            if device.connected {
+                device.written.push(settings.sens());
+                Ok(())
+            } else {
+                Err(io::Error::from_raw_os_error(ERROR_INVALID_HANDLE as _))
+            }
+        }
+
+        fn read(&mut self) -> io::Result<Settings> {
+            Ok(<_>::default())
+        }
+    }
+
+    #[test]
+    fn reconnects_with_backoff() {
+        let backend = FakeBackend::default();
+        backend.device().running = true;
+
+        let mut driver = Driver::connect(backend.clone()).unwrap();
+        let start = Instant::now();
+        let at = |millis| start + Duration::from_millis(millis);
+
+        assert!(driver.set_sens_at(2., at(0)).unwrap());
+        assert_eq!(backend.device().written, [2.]);
+
+        // the driver restarts, which breaks the handle
+        {
+            let mut device = backend.device();
+            device.running = false;
+            device.connected = false;
+        }
+
+        // reconnects right away once it notices
+        assert!(driver.set_sens_at(3., at(0)).is_err());
+        assert!(driver.backoff.is_some());
+        assert_eq!(backend.device().connects, 2);
+
+        // the next attempt is only due after the delay
+        assert!(driver.set_sens_at(4., at(100)).is_err());
+        driver.poll(at(499));
+        assert_eq!(backend.device().connects, 2);
+
+        driver.poll(at(500));
+        assert_eq!(backend.device().connects, 3);
+
+        backend.device().running = true;
+        driver.poll(at(1000));
+        assert_eq!(backend.device().connects, 3);
+
+        // the last sensitivity is applied again
+        driver.poll(at(1500));
+        assert!(driver.backoff.is_none());
+        assert_eq!(driver.error(), None);
+        assert_eq!(backend.device().written, [2., 4.]);
+
+        assert!(!driver.set_sens_at(4., at(1500)).unwrap())
     }
 }
@@ -1,6 +1,9 @@
+use std::{io, mem::size_of};
+
 const MAX_DEV_ID_LEN: usize = 200;
 
 #[repr(C)]
+#[derive(Copy, Clone)]
 struct Vec2<T> {
     x: T,
     y: T,
@@ -11,6 +14,10 @@ impl<T> Vec2<T> {
         Vec2 { x, y }
     }
 
+    fn try_map<U>(self, f: impl Fn(T) -> io::Result<U>) -> io::Result<Vec2<U>> {
+        Ok(Vec2::new(f(self.x)?, f(self.y)?))
+    }
+
     fn both(val: T) -> Vec2<T>
     where
         T: Clone,
@@ -49,7 +56,25 @@ struct AccelArgs {
     speed_cap: f64,
 }
 
+impl TryFrom<i32> for AccelMode {
+    type Error = io::Error;
+
+    fn try_from(mode: i32) -> io::Result<AccelMode> {
+        Ok(match mode {
+            0 => AccelMode::Linear,
+            1 => AccelMode::Classic,
+            2 => AccelMode::Natural,
+            3 => AccelMode::NaturalGain,
+            4 => AccelMode::Power,
+            5 => AccelMode::Motivity,
+            6 => AccelMode::NoAccel,
+            _ => return Err(invalid(format!("unknown acceleration mode {mode}"))),
+        })
+    }
+}
+
 #[repr(C)]
+#[derive(Copy, Clone)]
 #[allow(unused)]
 struct DomainArgs {
     domain_weights: Vec2<f64>,
@@ -73,11 +98,113 @@ pub struct Settings {
 }
 
 impl Settings {
+    pub fn sens(&self) -> f64 {
+        self.sens.x
+    }
+
     pub fn set_sens(&mut self, sens: f64) {
         self.sens = Vec2::both(sens)
     }
 }
 
+/// `Settings` with plain integers in place of enums and bools, since the
+/// driver may return any bytes
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct RawSettings {
+    degrees_rotation: f64,
+    degrees_snap: f64,
+    combine_mags: u8,
+    modes: Vec2<i32>,
+    argsv: Vec2<RawAccelArgs>,
+    sens: Vec2<f64>,
+    dir_multipliers: Vec2<f64>,
+    domain_args: DomainArgs,
+    range_weights: Vec2<f64>,
+    time_min: f64,
+    device_id: [u16; MAX_DEV_ID_LEN],
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct RawAccelArgs {
+    offset: f64,
+    legacy_offset: u8,
+    accel: f64,
+    scale: f64,
+    limit: f64,
+    exponent: f64,
+    midpoint: f64,
+    weight: f64,
+    scale_cap: f64,
+    gain_cap: f64,
+    speed_cap: f64,
+}
+
+const _: () = assert!(size_of::<RawSettings>() == size_of::<Settings>());
+
+impl RawSettings {
+    pub fn zeroed() -> RawSettings {
+        // all fields are numbers, for which zeroes are valid
+        unsafe { std::mem::zeroed() }
+    }
+}
+
+impl TryFrom<RawSettings> for Settings {
+    type Error = io::Error;
+
+    fn try_from(raw: RawSettings) -> io::Result<Settings> {
+        Ok(Settings {
+            degrees_rotation: raw.degrees_rotation,
+            degrees_snap: raw.degrees_snap,
+            combine_mags: flag(raw.combine_mags)?,
+            modes: raw.modes.try_map(AccelMode::try_from)?,
+            argsv: raw.argsv.try_map(AccelArgs::try_from)?,
+            sens: raw.sens,
+            dir_multipliers: raw.dir_multipliers,
+            domain_args: raw.domain_args,
+            range_weights: raw.range_weights,
+            time_min: raw.time_min,
+            device_id: raw.device_id,
+        })
+    }
+}
+
+impl TryFrom<RawAccelArgs> for AccelArgs {
+    type Error = io::Error;
+
+    fn try_from(raw: RawAccelArgs) -> io::Result<AccelArgs> {
+        Ok(AccelArgs {
+            offset: raw.offset,
+            legacy_offset: flag(raw.legacy_offset)?,
+            accel: raw.accel,
+            scale: raw.scale,
+            limit: raw.limit,
+            exponent: raw.exponent,
+            midpoint: raw.midpoint,
+            weight: raw.weight,
+            scale_cap: raw.scale_cap,
+            gain_cap: raw.gain_cap,
+            speed_cap: raw.speed_cap,
+        })
+    }
+}
+
+fn flag(value: u8) -> io::Result<bool> {
+    match value {
+        0 => Ok(false),
+        1 => Ok(true),
+        _ => Err(invalid(format!("invalid flag {value}"))),
+    }
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("invalid settings read from the driver: {message}"),
+    )
+}
+
 impl Default for Settings {
     fn default() -> Self {
         Settings {
@@ -110,3 +237,23 @@ impl Default for Settings {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn validates_raw_settings() {
+        let mut raw = RawSettings::zeroed();
+        raw.modes = Vec2::both(6);
+        raw.sens = Vec2::both(1.5);
+        assert_eq!(Settings::try_from(raw).unwrap().sens(), 1.5);
+
+        raw.modes.y = 7;
+        assert!(Settings::try_from(raw).is_err());
+
+        raw.modes.y = 0;
+        raw.argsv.x.legacy_offset = 2;
+        assert!(Settings::try_from(raw).is_err())
+    }
+}
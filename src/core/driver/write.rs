@@ -1,5 +1,5 @@
 use {
-    super::settings::Settings,
+    super::settings::{RawSettings, Settings},
     crate::windows::util::validate,
     std::{fs::File, io, mem::size_of, os::windows::io::AsRawHandle, ptr},
     winapi::{ctypes::c_void, um::ioapiset::DeviceIoControl},
@@ -8,7 +8,26 @@ use {
 pub fn write_settings(handle: &File, settings: &mut Settings) -> io::Result<()> {
     const RA_WRITE: u32 = 0x889;
 
-    device_io_control::<_, ()>(handle, rawaccel_code(RA_WRITE), Some(settings), None)
+    device_io_control::<_, ()>(handle, rawaccel_code(RA_WRITE), Some(settings), None).map(drop)
+}
+
+pub fn read_settings(handle: &File) -> io::Result<Settings> {
+    const RA_READ: u32 = 0x888;
+
+    let mut raw = RawSettings::zeroed();
+    let read = device_io_control::<(), _>(handle, rawaccel_code(RA_READ), None, Some(&mut raw))?;
+
+    if read as usize != size_of::<RawSettings>() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "the driver returned {read} bytes of settings instead of {}",
+                size_of::<RawSettings>()
+            ),
+        ))
+    }
+
+    raw.try_into()
 }
 
 fn rawaccel_code(code: u32) -> u32 {
@@ -24,9 +43,10 @@ fn device_io_control<I, O>(
     code: u32,
     input: Option<&mut I>,
     output: Option<&mut O>,
-) -> io::Result<()> {
+) -> io::Result<u32> {
     let input = SizedVoid::from(input);
     let output = SizedVoid::from(output);
+    let mut returned = 0;
 
     validate(unsafe {
         DeviceIoControl(
@@ -36,10 +56,12 @@ fn device_io_control<I, O>(
             input.size,
             output.ptr,
             output.size,
-            &mut 0,
+            &mut returned,
             ptr::null_mut(),
         )
-    })
+    })?;
+
+    Ok(returned)
 }
 
 struct SizedVoid {
@@ -66,6 +88,7 @@ mod test {
 
     #[test]
     fn sized_void_from() {
+        #[allow(unused)]
         struct Val([u8; 8], u64);
 
         let val = &mut Val([0; 8], 2);
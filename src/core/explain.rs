@@ -0,0 +1,173 @@
+use {
+    crate::{
+        cfg,
+        cli,
+        core::{
+            hook::{Context, Process, ProcessEvent, Running},
+            rules::{self, Resolution},
+        },
+        Result,
+    },
+    std::fmt,
+};
+
+/// prints which rules would be considered for a process in the given situation
+/// and which of them would be applied
+pub fn explain(args: cli::Explain) -> Result {
+    let config = cfg::read_existing_config()?;
+    super::load_script(&config);
+
+    let mut process = Process::new(0, args.process);
+    process.cursor_hidden = Some(args.cursor_hidden);
+    process.shell = rules::is_shell(process.exe(), "");
+
+    let background = args.background.then(|| process.exe().to_owned());
+    let mut running = Running::default();
+    running.apply(
+        args.running
+            .into_iter()
+            .chain(background)
+            .zip(1..)
+            .map(|(exe, id)| ProcessEvent::Started { id, exe }),
+    );
+
+    let context = Context {
+        focus: (!args.background).then_some(&process),
+        running: &running,
+        forced: None,
+        suspended: false,
+        // the actual cursor has nothing to do with the simulated situation
+        cursor_hidden: Some(args.cursor_hidden),
+    };
+
+    print!("{}", Explanation(&super::resolve(&config, &context)));
+
+    Ok(())
+}
+
+struct Explanation<'a>(&'a Resolution<'a>);
+
+impl fmt::Display for Explanation<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let resolution = self.0;
+
+        if resolution.candidates.is_empty() {
+            writeln!(f, "no entries match")?
+        } else {
+            writeln!(f, "entries considered:")?
+        }
+
+        for candidate in &resolution.candidates {
+            writeln!(f, "  {candidate}")?
+        }
+
+        write!(f, "sensitivity: {} ", resolution.sensitivity())?;
+
+        match (resolution.scripted, resolution.selected()) {
+            (Some(_), _) => writeln!(
+                f,
+                "(from the script, which takes precedence over the entries)"
+            ),
+            (None, Some(rule)) => writeln!(f, "(from the entry for {})", rule.key.display()),
+            (None, None) => writeln!(f, "({})", resolution.fallback),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        crate::cfg::{Activation, Config, Entry},
+    };
+
+    #[test]
+    fn explanation() {
+        let mut config = Config::default();
+        config.processes.extend([
+            ("game.exe".into(), Entry {
+                sensitivity: 2.,
+                ..<_>::default()
+            }),
+            (r"C:\game.exe".into(), Entry {
+                sensitivity: 3.,
+                ..<_>::default()
+            }),
+            ("paint.exe".into(), Entry {
+                sensitivity: 4.,
+                activation: Activation::Running,
+                ..<_>::default()
+            }),
+        ]);
+
+        let process = Process::new(0, r"C:\game.exe".into());
+        let mut running = Running::default();
+        running.apply([ProcessEvent::Started {
+            id: 1,
+            exe: "paint.exe".into(),
+        }]);
+        let context = Context {
+            focus: Some(&process),
+            running: &running,
+            forced: None,
+            suspended: false,
+            cursor_hidden: None,
+        };
+
+        assert_eq!(
+            Explanation(&rules::resolve(&config, &context)).to_string(),
+            "entries considered:
+  C:\\game.exe for C:\\game.exe (sensitivity 3, priority 0): selected
+  game.exe for C:\\game.exe (sensitivity 2, priority 0): rejected, shadowed by the entry for \
+             C:\\game.exe
+  paint.exe for paint.exe (sensitivity 4, priority 0): rejected, outranked by the entry for \
+             C:\\game.exe
+sensitivity: 3 (from the entry for C:\\game.exe)
+"
+        );
+
+        let context = Context {
+            focus: None,
+            running: &<_>::default(),
+            forced: None,
+            suspended: false,
+            cursor_hidden: None,
+        };
+
+        assert_eq!(
+            Explanation(&rules::resolve(&config, &context)).to_string(),
+            "no entries match\nsensitivity: 1 (default)\n"
+        );
+
+        // the actual cursor isn't considered for processes in the background
+        config.processes.insert("paint.exe".into(), Entry {
+            only_if_cursor_hidden: true,
+            activation: Activation::Running,
+            ..<_>::default()
+        });
+        let context = Context {
+            focus: None,
+            running: &running,
+            forced: None,
+            suspended: false,
+            cursor_hidden: Some(false),
+        };
+
+        assert_eq!(
+            Explanation(&rules::resolve(&config, &context)).to_string(),
+            "entries considered:
+  paint.exe for paint.exe (sensitivity 1, priority 0): rejected, only applies while the cursor is \
+             hidden
+sensitivity: 1 (default)
+"
+        );
+
+        // the script decides before the entries, like in the daemon
+        let mut resolution = rules::resolve(&config, &context);
+        resolution.scripted = Some(2.);
+
+        assert!(Explanation(&resolution).to_string().ends_with(
+            "sensitivity: 2 (from the script, which takes precedence over the entries)\n"
+        ))
+    }
+}
@@ -0,0 +1,218 @@
+use {
+    crate::{
+        core::hook::state::State,
+        output::report,
+        windows::process::{self, Exit},
+    },
+    std::{io, sync::MutexGuard, thread, time::Duration},
+};
+
+/// a source of process exit notifications
+pub trait ExitSource {
+    /// waits for the process to exit for at most `timeout` and returns whether
+    /// it did
+    fn wait_for_exit(&mut self, id: u32, timeout: Duration) -> io::Result<bool>;
+}
+
+/// waits for processes to exit using process handles
+#[derive(Default)]
+pub struct Handles {
+    /// the last process that was waited for, which is forgotten once it exits
+    current: Option<Watched>,
+}
+
+struct Watched {
+    id: u32,
+    /// when the process was created, if that could be queried, so a later
+    /// process with the same id isn't mistaken for it
+    created: Option<u64>,
+    /// `None` if the process couldn't be opened
+    exit: Option<Exit>,
+}
+
+impl ExitSource for Handles {
+    fn wait_for_exit(&mut self, id: u32, timeout: Duration) -> io::Result<bool> {
+        let created = match process::creation_time(id) {
+            Ok(created) => Some(created),
+            Err(process::Error::NotFound) => {
+                self.current = None;
+                return Ok(true)
+            }
+            Err(_) => None,
+        };
+
+        let cached = self
+            .current
+            .as_ref()
+            .is_some_and(|current| current.id == id && current.created == created);
+
+        if !cached {
+            let exit = match Exit::open(id) {
+                Ok(exit) => Some(exit),
+                // the process is already gone
+                Err(process::Error::NotFound) => {
+                    self.current = None;
+                    return Ok(true)
+                }
+                Err(e) => {
+                    self.current = Some(Watched {
+                        id,
+                        created,
+                        exit: None,
+                    });
+                    return Err(e.into())
+                }
+            };
+
+            self.current = Some(Watched { id, created, exit });
+        }
+
+        match self
+            .current
+            .as_ref()
+            .and_then(|current| current.exit.as_ref())
+        {
+            Some(exit) => {
+                let exited = exit.wait(timeout)?;
+
+                // the id may be reused once the handle is closed
+                if exited {
+                    self.current = None
+                }
+
+                Ok(exited)
+            }
+            None => {
+                thread::sleep(timeout);
+                Ok(false)
+            }
+        }
+    }
+}
+
+/// waits for the focused process to exit in the background and re-evaluates the
+/// rules when it does, until the hooks are removed
+pub fn watch_focus(mut source: impl ExitSource + Send + 'static, timeout: Duration) {
+    thread::spawn(move || while check_focus(State::get, &mut source, timeout) {});
+}
+
+/// returns `false` once there's no more state to check
+fn check_focus<'a>(
+    state: impl Fn() -> MutexGuard<'a, Option<State>>,
+    source: &mut impl ExitSource,
+    timeout: Duration,
+) -> bool {
+    let id = match state().as_ref() {
+        Some(state) => state.focus_id(),
+        None => return false,
+    };
+
+    let id = match id {
+        Some(id) => id,
+        None => {
+            thread::sleep(timeout);
+            return true
+        }
+    };
+
+    // the state isn't locked while waiting, so focus changes aren't blocked
+    match source.wait_for_exit(id, timeout) {
+        Ok(true) => {
+            if let Some(state) = state().as_mut() {
+                state.focus_exited(id)
+            }
+        }
+        Ok(false) => {}
+        Err(e) => report!(Warn, Process, "couldn't watch focused process: {e}"),
+    }
+
+    true
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        crate::{
+            cfg::Config,
+            core::{
+                driver::Driver,
+                hook::{Applied, Context, Process},
+            },
+            msg,
+        },
+        std::{cell::RefCell, path::PathBuf, sync::Mutex},
+    };
+
+    /// reports predetermined processes as exited
+    struct FakeExits {
+        exited: Vec<u32>,
+    }
+
+    impl ExitSource for FakeExits {
+        fn wait_for_exit(&mut self, id: u32, _timeout: Duration) -> io::Result<bool> {
+            Ok(self.exited.contains(&id))
+        }
+    }
+
+    thread_local! {
+        static FOCUSED: RefCell<Vec<Option<PathBuf>>> = <_>::default();
+    }
+
+    fn record(_: &Config, _: &mut Driver, context: &Context) -> Applied {
+        let exe = context.focus.map(|process| process.exe().to_owned());
+        FOCUSED.with(|focused| focused.borrow_mut().push(exe));
+
+        Applied {
+            entry: None,
+            source: msg::Source::Default,
+        }
+    }
+
+    #[test]
+    fn reevaluates_when_focus_exits() {
+        let state = Mutex::new(Some(State::new(
+            <_>::default(),
+            Driver::dummy().unwrap(),
+            record,
+        )));
+        let lock = || state.lock().unwrap();
+
+        lock()
+            .as_mut()
+            .unwrap()
+            .set_focus(Process::new(7, "game.exe".into()));
+
+        let mut source = FakeExits { exited: vec![] };
+        assert!(check_focus(lock, &mut source, Duration::ZERO));
+
+        source.exited.push(7);
+        assert!(check_focus(lock, &mut source, Duration::ZERO));
+        // nothing is focused anymore, so there's nothing to re-evaluate
+        assert!(check_focus(lock, &mut source, Duration::ZERO));
+
+        assert_eq!(FOCUSED.with(RefCell::take), [Some("game.exe".into()), None]);
+
+        lock().take();
+        assert!(!check_focus(lock, &mut source, Duration::ZERO))
+    }
+
+    #[test]
+    fn handles_keep_running_process() {
+        let mut handles = Handles::default();
+        let id = std::process::id();
+
+        assert!(!handles.wait_for_exit(id, Duration::ZERO).unwrap());
+        assert!(!handles.wait_for_exit(id, Duration::ZERO).unwrap());
+        assert!(handles
+            .current
+            .as_ref()
+            .is_some_and(|current| current.id == id && current.exit.is_some()))
+    }
+
+    #[test]
+    fn handles_report_exit_of_missing_process() {
+        // process ids are multiples of 4, so this one can't exist
+        assert!(Handles::default().wait_for_exit(3, Duration::ZERO).unwrap())
+    }
+}
@@ -0,0 +1,224 @@
+//! which of the inputs that entries watch are held down in the focused process
+
+use {
+    crate::{
+        cfg::{self, Button, Config},
+        core::hotkeys::Key,
+        output::report,
+    },
+    std::fmt,
+    winapi::{
+        shared::minwindef::HIWORD,
+        um::winuser::{
+            VK_CONTROL,
+            VK_LCONTROL,
+            VK_LMENU,
+            VK_LSHIFT,
+            VK_MENU,
+            VK_RCONTROL,
+            VK_RMENU,
+            VK_RSHIFT,
+            VK_SHIFT,
+            WM_KEYDOWN,
+            WM_KEYUP,
+            WM_LBUTTONDOWN,
+            WM_LBUTTONUP,
+            WM_MBUTTONDOWN,
+            WM_MBUTTONUP,
+            WM_RBUTTONDOWN,
+            WM_RBUTTONUP,
+            WM_SYSKEYDOWN,
+            WM_SYSKEYUP,
+            WM_XBUTTONDOWN,
+            WM_XBUTTONUP,
+            XBUTTON1,
+        },
+    },
+};
+
+const MODIFIERS: [(&str, i32); 4] = [
+    ("Shift", VK_SHIFT),
+    ("Ctrl", VK_CONTROL),
+    ("Control", VK_CONTROL),
+    ("Alt", VK_MENU),
+];
+
+/// a mouse button or key as it's reported by the input hooks
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Input {
+    Button(Button),
+    /// a virtual-key code, where left and right modifiers aren't distinguished
+    Key(u32),
+}
+
+impl Input {
+    pub fn from_config(input: &cfg::Input) -> Result<Input, String> {
+        let name = match input {
+            cfg::Input::Button(button) => return Ok(Input::Button(*button)),
+            cfg::Input::Key(name) => name,
+        };
+
+        match MODIFIERS
+            .iter()
+            .find(|(modifier, _)| modifier.eq_ignore_ascii_case(name))
+        {
+            Some(&(_, code)) => Ok(Input::Key(code as u32)),
+            None => name.parse::<Key>().map(|key| Input::Key(key.code())),
+        }
+    }
+
+    /// the input and whether it was pressed for a low-level mouse message, if
+    /// it's about a button
+    pub fn from_mouse(message: u32, data: u32) -> Option<(Input, bool)> {
+        let (button, pressed) = match message {
+            WM_LBUTTONDOWN => (Button::Left, true),
+            WM_LBUTTONUP => (Button::Left, false),
+            WM_RBUTTONDOWN => (Button::Right, true),
+            WM_RBUTTONUP => (Button::Right, false),
+            WM_MBUTTONDOWN => (Button::Middle, true),
+            WM_MBUTTONUP => (Button::Middle, false),
+            WM_XBUTTONDOWN | WM_XBUTTONUP => {
+                let button = if HIWORD(data) == XBUTTON1 {
+                    Button::X1
+                } else {
+                    Button::X2
+                };
+
+                (button, message == WM_XBUTTONDOWN)
+            }
+            _ => return None,
+        };
+
+        Some((Input::Button(button), pressed))
+    }
+
+    /// the input and whether it was pressed for a low-level keyboard message
+    pub fn from_key(message: u32, code: u32) -> Option<(Input, bool)> {
+        let pressed = match message {
+            WM_KEYDOWN | WM_SYSKEYDOWN => true,
+            WM_KEYUP | WM_SYSKEYUP => false,
+            _ => return None,
+        };
+
+        let code = match code as i32 {
+            VK_LSHIFT | VK_RSHIFT => VK_SHIFT as u32,
+            VK_LCONTROL | VK_RCONTROL => VK_CONTROL as u32,
+            VK_LMENU | VK_RMENU => VK_MENU as u32,
+            _ => code,
+        };
+
+        Some((Input::Key(code), pressed))
+    }
+}
+
+impl fmt::Display for cfg::Input {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            cfg::Input::Button(button) => {
+                write!(f, "the {} button", format!("{button:?}").to_lowercase())
+            }
+            cfg::Input::Key(name) => name.fmt(f),
+        }
+    }
+}
+
+/// the inputs the entries in `config` watch, reporting the ones that aren't
+/// valid
+pub fn watched(config: &Config) -> Vec<Input> {
+    let mut inputs = Vec::new();
+
+    for (key, entry) in &config.processes {
+        let Some(while_held) = &entry.while_held else {
+            continue
+        };
+
+        match Input::from_config(&while_held.input) {
+            Ok(input) if !inputs.contains(&input) => inputs.push(input),
+            Ok(_) => {}
+            Err(e) => report!(
+                Warn,
+                Config,
+                "ignoring while_held of the entry for {}: {e}",
+                key.display()
+            ),
+        }
+    }
+
+    inputs
+}
+
+/// the inputs that were pressed, and not released since
+#[derive(Debug, Default)]
+pub struct Held {
+    down: Vec<Input>,
+}
+
+impl Held {
+    /// returns whether the input wasn't held already, which it may be because
+    /// of key repeats
+    pub fn press(&mut self, input: Input) -> bool {
+        let pressed = !self.down.contains(&input);
+
+        if pressed {
+            self.down.push(input)
+        }
+
+        pressed
+    }
+
+    /// returns whether the input was held
+    pub fn release(&mut self, input: Input) -> bool {
+        let len = self.down.len();
+        self.down.retain(|&down| down != input);
+        self.down.len() != len
+    }
+
+    pub fn holds(&self, input: &cfg::Input) -> bool {
+        Input::from_config(input).is_ok_and(|input| self.down.contains(&input))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {super::*, winapi::um::winuser::WM_MOUSEMOVE};
+
+    #[test]
+    fn parse_inputs() {
+        let key = |name: &str| Input::from_config(&cfg::Input::Key(name.into()));
+
+        assert_eq!(
+            Input::from_config(&cfg::Input::Button(Button::Right)),
+            Ok(Input::Button(Button::Right))
+        );
+        assert_eq!(key("shift"), Ok(Input::Key(VK_SHIFT as u32)));
+        assert_eq!(key("e"), Ok(Input::Key('E' as u32)));
+        assert_eq!(key("Hyper"), Err("unknown key Hyper".into()));
+
+        assert_eq!(
+            Input::from_key(WM_KEYDOWN, VK_RSHIFT as u32),
+            Some((Input::Key(VK_SHIFT as u32), true))
+        );
+        assert_eq!(
+            Input::from_mouse(WM_XBUTTONUP, (XBUTTON1 as u32) << 16),
+            Some((Input::Button(Button::X1), false))
+        );
+        assert_eq!(Input::from_mouse(WM_MOUSEMOVE, 0), None)
+    }
+
+    #[test]
+    fn press_and_release() {
+        let mut held = Held::default();
+        let right = Input::Button(Button::Right);
+        let config = cfg::Input::Button(Button::Right);
+
+        assert!(held.press(right));
+        assert!(held.holds(&config));
+
+        // repeated presses don't change anything
+        assert!(!held.press(right));
+
+        assert!(held.release(right));
+        assert!(!held.holds(&config));
+        assert!(!held.release(right))
+    }
+}
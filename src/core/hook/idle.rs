@@ -0,0 +1,116 @@
+//! detects when no input was received for a while
+
+use {
+    crate::windows::util::uninit_sized,
+    std::time::Duration,
+    winapi::um::{
+        sysinfoapi::GetTickCount64,
+        winuser::{GetLastInputInfo, LASTINPUTINFO},
+    },
+};
+
+/// a source of the current time and the time of the last input, relative to
+/// the same arbitrary point
+pub trait Clock {
+    fn now(&self) -> Duration;
+    fn last_input(&self) -> Duration;
+}
+
+/// the system's tick count, which is what the last input is reported in
+#[derive(Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        Duration::from_millis(unsafe { GetTickCount64() })
+    }
+
+    fn last_input(&self) -> Duration {
+        let mut info = unsafe { uninit_sized::<LASTINPUTINFO>(|info| &mut info.cbSize) };
+        let now = unsafe { GetTickCount64() };
+
+        if unsafe { GetLastInputInfo(&mut info) } == 0 {
+            return Duration::from_millis(now)
+        }
+
+        // the time of the last input only has 32 bits, so it wraps around
+        let since = (now as u32).wrapping_sub(info.dwTime);
+
+        Duration::from_millis(now.saturating_sub(since as u64))
+    }
+}
+
+/// whether no input was received for longer than the timeout
+#[derive(Debug, Default)]
+pub struct Idle {
+    idle: bool,
+}
+
+impl Idle {
+    /// returns whether the user became idle or active again, if either
+    /// happened. without a timeout, they're never idle.
+    pub fn update(&mut self, timeout: Option<Duration>, clock: &impl Clock) -> Option<bool> {
+        let idle = timeout
+            .is_some_and(|timeout| clock.now().saturating_sub(clock.last_input()) >= timeout);
+
+        (idle != self.idle).then(|| {
+            self.idle = idle;
+            idle
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {super::*, std::cell::Cell};
+
+    #[derive(Default)]
+    struct FakeClock {
+        now: Cell<Duration>,
+        last_input: Cell<Duration>,
+    }
+
+    impl FakeClock {
+        fn advance(&self, secs: u64) {
+            self.now.set(self.now.get() + Duration::from_secs(secs))
+        }
+
+        fn input(&self) {
+            self.last_input.set(self.now.get())
+        }
+    }
+
+    impl Clock for FakeClock {
+        fn now(&self) -> Duration {
+            self.now.get()
+        }
+
+        fn last_input(&self) -> Duration {
+            self.last_input.get()
+        }
+    }
+
+    #[test]
+    fn idle_timeout() {
+        let clock = FakeClock::default();
+        let timeout = Some(Duration::from_secs(60));
+        let mut idle = Idle::default();
+
+        clock.advance(59);
+        assert_eq!(idle.update(timeout, &clock), None);
+
+        clock.advance(1);
+        assert_eq!(idle.update(timeout, &clock), Some(true));
+        clock.advance(60);
+        assert_eq!(idle.update(timeout, &clock), None);
+
+        clock.input();
+        assert_eq!(idle.update(timeout, &clock), Some(false));
+
+        // disabling it while idle ends it
+        clock.advance(60);
+        assert_eq!(idle.update(timeout, &clock), Some(true));
+        assert_eq!(idle.update(None, &clock), Some(false));
+        assert_eq!(idle.update(None, &clock), None)
+    }
+}
@@ -0,0 +1,224 @@
+use {
+    crate::windows::process::{self, Error},
+    std::{fmt, path::PathBuf},
+};
+
+/// a way of determining the executable of a process
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LookupMethod {
+    /// querying the full path, which requires opening the process
+    FullPath,
+    /// finding the file name in a snapshot of all processes, which also works
+    /// for processes that can't be opened (e.g. elevated ones)
+    Snapshot,
+}
+
+impl fmt::Display for LookupMethod {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LookupMethod::FullPath => "full path query",
+            LookupMethod::Snapshot => "process snapshot",
+        }
+        .fmt(f)
+    }
+}
+
+pub trait ExeProvider {
+    fn method(&self) -> LookupMethod;
+    fn exe_path(&self, id: u32) -> process::Result<PathBuf>;
+}
+
+pub struct FullPath;
+
+impl ExeProvider for FullPath {
+    fn method(&self) -> LookupMethod {
+        LookupMethod::FullPath
+    }
+
+    fn exe_path(&self, id: u32) -> process::Result<PathBuf> {
+        process::exe_path(id)
+    }
+}
+
+pub struct Snapshot;
+
+impl ExeProvider for Snapshot {
+    fn method(&self) -> LookupMethod {
+        LookupMethod::Snapshot
+    }
+
+    fn exe_path(&self, id: u32) -> process::Result<PathBuf> {
+        process::snapshot()
+            .map_err(Error::Other)?
+            .into_iter()
+            .find(|proc| proc.id == id)
+            .map(|proc| proc.exe_name)
+            .ok_or(Error::NotFound)
+    }
+}
+
+/// the providers used to look up the executable of a focused process, in order
+pub const PROVIDERS: [&dyn ExeProvider; 2] = [&FullPath, &Snapshot];
+
+#[derive(Debug)]
+pub struct Lookup {
+    pub id: u32,
+    /// the executable and the method that found it
+    pub exe: Option<(PathBuf, LookupMethod)>,
+    /// the methods that were tried unsuccessfully before
+    pub failures: Vec<(LookupMethod, Error)>,
+}
+
+/// tries each provider in order until one of them finds the executable
+pub fn lookup(providers: &[&dyn ExeProvider], id: u32) -> Lookup {
+    let mut failures = Vec::new();
+
+    // the idle process, which snapshots list as "[System Process]", is what
+    // windows without a process report
+    if id == 0 {
+        return Lookup {
+            id,
+            exe: None,
+            failures,
+        }
+    }
+
+    for provider in providers {
+        match provider.exe_path(id) {
+            Ok(exe) => {
+                return Lookup {
+                    id,
+                    exe: Some((exe, provider.method())),
+                    failures,
+                }
+            }
+            Err(e) => failures.push((provider.method(), e)),
+        }
+    }
+
+    Lookup {
+        id,
+        exe: None,
+        failures,
+    }
+}
+
+impl Lookup {
+    /// whether the executable was found and the providers before only failed
+    /// because the process can't be opened, which is normal for elevated ones
+    pub fn expected(&self) -> bool {
+        self.exe.is_some()
+            && self
+                .failures
+                .iter()
+                .all(|(_, e)| matches!(e, Error::AccessDenied))
+    }
+}
+
+impl fmt::Display for Lookup {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.exe {
+            Some((exe, method)) => write!(f, "found {} via {method}", exe.display())?,
+            None => write!(f, "couldn't determine executable of process {}", self.id)?,
+        }
+
+        for (method, e) in &self.failures {
+            write!(f, " ({method} failed: {e})")?
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct Fake {
+        method: LookupMethod,
+        exe: Option<&'static str>,
+    }
+
+    impl ExeProvider for Fake {
+        fn method(&self) -> LookupMethod {
+            self.method
+        }
+
+        fn exe_path(&self, _id: u32) -> process::Result<PathBuf> {
+            self.exe.map(<_>::into).ok_or(Error::AccessDenied)
+        }
+    }
+
+    const DENIED: Fake = Fake {
+        method: LookupMethod::FullPath,
+        exe: None,
+    };
+
+    const NAME: Fake = Fake {
+        method: LookupMethod::Snapshot,
+        exe: Some("game.exe"),
+    };
+
+    #[test]
+    fn falls_back() {
+        let lookup = lookup(&[&DENIED, &NAME], 4);
+
+        assert_eq!(
+            lookup.exe,
+            Some(("game.exe".into(), LookupMethod::Snapshot))
+        );
+        assert_eq!(lookup.failures.len(), 1);
+        assert!(lookup.expected());
+        assert_eq!(
+            lookup.to_string(),
+            "found game.exe via process snapshot (full path query failed: access denied)"
+        )
+    }
+
+    #[test]
+    fn stops_at_first_success() {
+        let path = Fake {
+            method: LookupMethod::FullPath,
+            exe: Some(r"C:\game.exe"),
+        };
+        let lookup = lookup(&[&path, &NAME], 4);
+
+        assert_eq!(
+            lookup.exe,
+            Some((r"C:\game.exe".into(), LookupMethod::FullPath))
+        );
+        assert!(lookup.failures.is_empty())
+    }
+
+    #[test]
+    fn all_fail() {
+        let lookup = lookup(&[&DENIED], 4);
+
+        assert!(lookup.exe.is_none() && !lookup.expected());
+        assert_eq!(
+            lookup.to_string(),
+            "couldn't determine executable of process 4 (full path query failed: access denied)"
+        )
+    }
+
+    #[test]
+    fn skips_idle_process() {
+        let lookup = lookup(&[&NAME], 0);
+
+        assert!(lookup.exe.is_none() && lookup.failures.is_empty())
+    }
+
+    #[test]
+    fn real_providers_find_current_process() {
+        let lookup = lookup(&PROVIDERS, std::process::id());
+
+        assert_eq!(
+            lookup.exe,
+            Some((std::env::current_exe().unwrap(), LookupMethod::FullPath))
+        );
+        assert_eq!(
+            Snapshot.exe_path(std::process::id()).unwrap().file_name(),
+            std::env::current_exe().unwrap().file_name()
+        )
+    }
+}
@@ -1,34 +1,107 @@
 use {
-    crate::{cfg::Config, core::driver::Driver, windows},
+    crate::{
+        cfg::Config,
+        core::{
+            cursor,
+            driver::Driver,
+            rules::{self, Override},
+            script,
+        },
+        msg,
+        output::report,
+        windows::window::Window,
+    },
+    held::{Held, Input},
+    idle::{Clock, Idle},
     state::State,
     std::{
         path::{Path, PathBuf},
         ptr,
+        sync::{
+            mpsc::{self, Sender},
+            OnceLock,
+        },
+        thread,
+        time::{Duration, Instant},
     },
+    system::SystemEvents,
     winapi::{
-        shared::windef::{HWINEVENTHOOK, HWND},
-        um::winuser::{
-            GetWindowThreadProcessId,
-            SetWinEventHook,
-            UnhookWinEvent,
-            CHILDID_SELF,
-            EVENT_OBJECT_HIDE,
-            EVENT_OBJECT_SHOW,
-            EVENT_SYSTEM_FOREGROUND,
-            OBJID_CURSOR,
-            WINEVENT_OUTOFCONTEXT,
-            WINEVENT_SKIPOWNPROCESS,
+        shared::{
+            minwindef::{LPARAM, LRESULT, WPARAM},
+            windef::{HHOOK, HWINEVENTHOOK, HWND},
+        },
+        um::{
+            libloaderapi::GetModuleHandleA,
+            winuser::{
+                CallNextHookEx,
+                GetForegroundWindow,
+                GetWindowThreadProcessId,
+                SetWinEventHook,
+                SetWindowsHookExA,
+                UnhookWinEvent,
+                UnhookWindowsHookEx,
+                CHILDID_SELF,
+                EVENT_OBJECT_HIDE,
+                EVENT_OBJECT_NAMECHANGE,
+                EVENT_OBJECT_SHOW,
+                EVENT_SYSTEM_FOREGROUND,
+                HC_ACTION,
+                KBDLLHOOKSTRUCT,
+                MSLLHOOKSTRUCT,
+                OBJID_CURSOR,
+                OBJID_WINDOW,
+                WH_KEYBOARD_LL,
+                WH_MOUSE_LL,
+                WINEVENT_OUTOFCONTEXT,
+                WINEVENT_SKIPOWNPROCESS,
+            },
         },
     },
 };
 
+pub use {
+    exit::{watch_focus, Handles},
+    idle::SystemClock,
+    running::{ProcessEvent, ProcessList, Running, Snapshots},
+    system::Notifications,
+};
+
+mod exit;
+pub mod held;
+mod idle;
+mod lookup;
+pub mod running;
 mod state;
+mod system;
 
-pub type Handler = fn(&Config, &mut Driver, &Process);
+pub type Handler = fn(&Config, &mut Driver, &Context) -> Applied;
+
+/// what the handler applied, which is what the status reports
+#[derive(Debug, Clone, PartialEq)]
+pub struct Applied {
+    /// the key of the entry the sensitivity comes from, if any
+    pub entry: Option<PathBuf>,
+    pub source: msg::Source,
+}
+
+pub struct Context<'a> {
+    pub focus: Option<&'a Process>,
+    pub running: &'a Running,
+    pub forced: Option<&'a Override>,
+    /// whether scaling is paused or idle, in which case only the default
+    /// sensitivity applies
+    pub suspended: bool,
+    /// whether the cursor is hidden while nothing is focused, which is looked
+    /// up if it isn't known
+    pub cursor_hidden: Option<bool>,
+}
 
 pub struct Hooks {
     focus: HWINEVENTHOOK,
     visibility: HWINEVENTHOOK,
+    title: HWINEVENTHOOK,
+    mouse: Option<InputHook>,
+    keyboard: Option<InputHook>,
 }
 
 impl Hooks {
@@ -47,14 +120,88 @@ impl Hooks {
             on_focus_changed,
         );
         let visibility = set_hook(EVENT_OBJECT_SHOW, EVENT_OBJECT_HIDE, on_visibility_changed);
+        let title = set_hook(
+            EVENT_OBJECT_NAMECHANGE,
+            EVENT_OBJECT_NAMECHANGE,
+            on_title_changed,
+        );
+        drop(state);
+
+        let mut hooks = Hooks {
+            focus,
+            visibility,
+            title,
+            mouse: None,
+            keyboard: None,
+        };
+        hooks.watch_input();
 
-        Hooks { focus, visibility }.into()
+        hooks.into()
     }
 
     pub fn set_config(&mut self, config: Config) {
         if let Some(state) = State::get().as_mut() {
-            state.config = config
+            state.set_config(config)
+        }
+
+        self.watch_input()
+    }
+
+    /// changes the config and applies the result, unlike `set_config`
+    pub fn update_config(&mut self, f: impl FnOnce(&mut Config)) {
+        if let Some(state) = State::get().as_mut() {
+            state.update_config(f)
         }
+
+        self.watch_input()
+    }
+
+    /// hooks mouse and keyboard input only while the config watches it, since
+    /// all input passes through the hooks
+    fn watch_input(&mut self) {
+        let (buttons, keys) = State::get()
+            .as_ref()
+            .map_or((false, false), State::watches_input);
+
+        if buttons || keys {
+            INPUTS.get_or_init(apply_inputs);
+        }
+
+        if buttons != self.mouse.is_some() {
+            self.mouse = buttons
+                .then(|| InputHook::set(WH_MOUSE_LL, on_mouse_input))
+                .flatten()
+        }
+
+        if keys != self.keyboard.is_some() {
+            self.keyboard = keys
+                .then(|| InputHook::set(WH_KEYBOARD_LL, on_key_input))
+                .flatten()
+        }
+    }
+
+    pub fn set_override(&mut self, forced: Option<Override>) {
+        if let Some(state) = State::get().as_mut() {
+            state.set_override(forced)
+        }
+    }
+
+    /// returns whether pausing or resuming changed anything
+    pub fn set_paused(&mut self, paused: bool) -> bool {
+        State::get()
+            .as_mut()
+            .is_some_and(|state| state.set_paused(paused))
+    }
+
+    pub fn paused(&self) -> bool {
+        State::get().as_ref().is_some_and(State::paused)
+    }
+
+    pub fn query<T>(
+        &self,
+        f: impl FnOnce(&Config, &Driver, &Context, Option<&Applied>) -> T,
+    ) -> Option<T> {
+        State::get().as_ref().map(|state| state.query(f))
     }
 }
 
@@ -76,33 +223,170 @@ fn set_hook(
     }
 }
 
+/// a low-level input hook, which is removed when dropped
+struct InputHook(HHOOK);
+
+impl InputHook {
+    fn set(
+        id: i32,
+        handler: unsafe extern "system" fn(i32, WPARAM, LPARAM) -> LRESULT,
+    ) -> Option<InputHook> {
+        let hook =
+            unsafe { SetWindowsHookExA(id, Some(handler), GetModuleHandleA(ptr::null()), 0) };
+
+        if hook.is_null() {
+            report!(
+                Error,
+                General,
+                "couldn't hook input: {}",
+                std::io::Error::last_os_error()
+            );
+            return None
+        }
+
+        Some(InputHook(hook))
+    }
+}
+
+impl Drop for InputHook {
+    fn drop(&mut self) {
+        unsafe { UnhookWindowsHookEx(self.0) };
+    }
+}
+
 impl Drop for Hooks {
     fn drop(&mut self) {
         unsafe {
             UnhookWinEvent(self.focus);
             UnhookWinEvent(self.visibility);
+            UnhookWinEvent(self.title);
         }
 
         State::get().take();
     }
 }
 
+/// polls the process list in the background and keeps track of running
+/// processes until the hooks are removed
+pub fn watch_processes(mut list: impl ProcessList + Send + 'static, interval: Duration) {
+    thread::spawn(move || loop {
+        let needed = match State::get().as_ref() {
+            Some(state) => state.needs_running(),
+            None => break,
+        };
+
+        // the state isn't locked while polling, since that can take a while
+        if needed {
+            match list.poll() {
+                Ok(events) => {
+                    if let Some(state) = State::get().as_mut() {
+                        state.update_running(events)
+                    }
+                }
+                Err(e) => report!(Error, Process, "couldn't list processes: {e}"),
+            }
+        }
+
+        thread::sleep(interval)
+    });
+}
+
+/// applies the default sensitivity while no input is received for the
+/// configured time, until the hooks are removed
+pub fn watch_idle(clock: impl Clock + Send + 'static, interval: Duration) {
+    thread::spawn(move || {
+        let mut idle = Idle::default();
+
+        loop {
+            match State::get().as_mut() {
+                Some(state) => {
+                    if let Some(idle) = idle.update(state.idle_timeout(), &clock) {
+                        state.set_idle(idle)
+                    }
+                }
+                None => break,
+            }
+
+            thread::sleep(interval)
+        }
+    });
+}
+
+/// applies the current state again once the session is unlocked or the system
+/// resumes, since the driver may have been reset and the focus may have changed
+/// without notice, until the hooks are removed
+pub fn watch_system(mut events: impl SystemEvents + Send + 'static) {
+    thread::spawn(move || {
+        system::forward(&mut events, |_| {
+            let window = unsafe { GetForegroundWindow() };
+            let focus = (!window.is_null()).then(|| focused_process(window));
+
+            State::get()
+                .as_mut()
+                .map(|state| state.refresh(focus))
+                .is_some()
+        })
+    });
+}
+
+/// reconnects to the driver once it's available again if the connection broke,
+/// until the hooks are removed
+pub fn watch_driver(interval: Duration) {
+    thread::spawn(move || loop {
+        match State::get().as_mut() {
+            Some(state) => state.poll_driver(Instant::now()),
+            None => break,
+        }
+
+        thread::sleep(interval)
+    });
+}
+
+/// clears the override once it expires, until the hooks are removed
+pub fn watch_override(interval: Duration) {
+    thread::spawn(move || loop {
+        match State::get().as_mut() {
+            Some(state) => state.expire_override(Instant::now()),
+            None => break,
+        }
+
+        thread::sleep(interval)
+    });
+}
+
 pub struct Process {
+    id: u32,
     path: PathBuf,
     pub cursor_hidden: Option<bool>,
+    /// the window that received focus
+    pub window: Option<Window>,
+    /// the watched inputs that were pressed while the process was focused
+    pub held: Held,
+    /// whether the process is the shell, or the focused window belongs to it
+    pub shell: bool,
 }
 
 impl Process {
-    fn new(path: PathBuf) -> Process {
+    pub fn new(id: u32, path: PathBuf) -> Process {
         Process {
+            id,
             path,
             cursor_hidden: None,
+            window: None,
+            held: <_>::default(),
+            shell: false,
         }
     }
 
     pub fn exe(&self) -> &Path {
         &self.path
     }
+
+    /// whether the cursor is hidden, querying it if no change was observed
+    /// while the process was focused
+    pub fn cursor_hidden(&self) -> bool {
+        self.cursor_hidden.unwrap_or_else(cursor::hidden)
+    }
 }
 
 unsafe extern "system" fn on_focus_changed(
@@ -114,17 +398,44 @@ unsafe extern "system" fn on_focus_changed(
     _event_thread: u32,
     _event_time: u32,
 ) {
-    let mut proc = 0;
-    GetWindowThreadProcessId(window, &mut proc);
-    let path = windows::process::exe_path(proc).unwrap_or_default();
-
-    let process = Process::new(path);
+    let process = focused_process(window);
+    let exe = Some(process.exe()).filter(|exe| !exe.as_os_str().is_empty());
+    msg::publish(msg::Event::FocusChanged {
+        exe: exe.map(<_>::to_owned),
+    });
 
     if let Some(state) = State::get().as_mut() {
         state.set_focus(process)
     }
 }
 
+/// looks up the process the focused window belongs to, without publishing
+/// the change, since the focus is also looked up again after unlocking
+fn focused_process(window: HWND) -> Process {
+    let mut proc = 0;
+    unsafe { GetWindowThreadProcessId(window, &mut proc) };
+
+    let lookup = lookup::lookup(&lookup::PROVIDERS, proc);
+
+    if !lookup.failures.is_empty() {
+        // elevated processes are focused often enough for this to be noise
+        if lookup.expected() {
+            report!(Debug, Process, "{lookup}")
+        } else {
+            report!(Warn, Process, "{lookup}")
+        }
+    }
+
+    let path = lookup.exe.map(|(exe, _)| exe).unwrap_or_default();
+    report!(Debug, Focus, "focused {} ({proc})", path.display());
+    let window = Window::new(window);
+    let mut process = Process::new(proc, path);
+    process.shell = rules::is_shell(process.exe(), &window.class());
+    process.window = Some(window);
+
+    process
+}
+
 unsafe extern "system" fn on_visibility_changed(
     _hook: HWINEVENTHOOK,
     event: u32,
@@ -141,18 +452,92 @@ unsafe extern "system" fn on_visibility_changed(
     }
 }
 
+unsafe extern "system" fn on_title_changed(
+    _hook: HWINEVENTHOOK,
+    _event: u32,
+    window: HWND,
+    object: i32,
+    child: i32,
+    _event_thread: u32,
+    _event_time: u32,
+) {
+    // only the script sees titles, and they change often
+    if object == OBJID_WINDOW && child == CHILDID_SELF && script::loaded() {
+        if let Some(state) = State::get().as_mut() {
+            state.title_changed(Window::new(window))
+        }
+    }
+}
+
+unsafe extern "system" fn on_mouse_input(code: i32, message: WPARAM, data: LPARAM) -> LRESULT {
+    if code == HC_ACTION {
+        let info = &*(data as *const MSLLHOOKSTRUCT);
+
+        if let Some((input, pressed)) = Input::from_mouse(message as u32, info.mouseData) {
+            set_input(input, pressed)
+        }
+    }
+
+    CallNextHookEx(ptr::null_mut(), code, message, data)
+}
+
+unsafe extern "system" fn on_key_input(code: i32, message: WPARAM, data: LPARAM) -> LRESULT {
+    if code == HC_ACTION {
+        let info = &*(data as *const KBDLLHOOKSTRUCT);
+
+        if let Some((input, pressed)) = Input::from_key(message as u32, info.vkCode) {
+            set_input(input, pressed)
+        }
+    }
+
+    CallNextHookEx(ptr::null_mut(), code, message, data)
+}
+
+/// the inputs recorded by the input hooks, which only send them, since they
+/// stall all input until they return and are removed if they take too long
+static INPUTS: OnceLock<Sender<(Input, bool)>> = OnceLock::new();
+
+/// applies the inputs the hooks record on a thread of its own
+fn apply_inputs() -> Sender<(Input, bool)> {
+    let (sender, receiver) = mpsc::channel();
+
+    thread::spawn(move || {
+        for (input, pressed) in receiver {
+            if let Some(state) = State::get().as_mut() {
+                state.set_input(input, pressed)
+            }
+        }
+    });
+
+    sender
+}
+
+fn set_input(input: Input, pressed: bool) {
+    if let Some(inputs) = INPUTS.get() {
+        let _ = inputs.send((input, pressed));
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
 
     #[test]
     fn hooks_lifecycle() {
-        let set_hooks = || Hooks::set(<_>::default(), Driver::dummy().unwrap(), |_, _, _| {});
+        let set_hooks = || {
+            Hooks::set(<_>::default(), Driver::dummy().unwrap(), |_, _, _| {
+                Applied {
+                    entry: None,
+                    source: msg::Source::Default,
+                }
+            })
+        };
 
         let hooks = set_hooks().unwrap();
 
         assert!(!hooks.focus.is_null());
         assert!(!hooks.visibility.is_null());
+        assert!(!hooks.title.is_null());
 
         assert!(set_hooks().is_none());
 
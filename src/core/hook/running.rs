@@ -0,0 +1,138 @@
+use {
+    crate::windows,
+    std::{
+        collections::HashMap,
+        io,
+        path::{Path, PathBuf},
+    },
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProcessEvent {
+    Started { id: u32, exe: PathBuf },
+    Exited { id: u32 },
+}
+
+/// a source of process start and exit events
+pub trait ProcessList {
+    /// returns all events that occurred since the last call
+    fn poll(&mut self) -> io::Result<Vec<ProcessEvent>>;
+}
+
+/// detects started and exited processes by comparing consecutive snapshots of
+/// the system's process list
+#[derive(Default)]
+pub struct Snapshots {
+    known: HashMap<u32, PathBuf>,
+}
+
+impl ProcessList for Snapshots {
+    fn poll(&mut self) -> io::Result<Vec<ProcessEvent>> {
+        let current = windows::process::snapshot()?
+            .into_iter()
+            .map(|proc| (proc.id, proc.exe_name))
+            .collect::<HashMap<_, _>>();
+
+        let exited = self
+            .known
+            .iter()
+            .filter(|(id, name)| current.get(id) != Some(name))
+            .map(|(&id, _)| ProcessEvent::Exited { id });
+
+        let started = current
+            .iter()
+            .filter(|(id, name)| self.known.get(id) != Some(name))
+            .map(|(&id, name)| ProcessEvent::Started {
+                id,
+                exe: windows::process::exe_path(id).unwrap_or_else(|_| name.clone()),
+            });
+
+        let events = exited.chain(started).collect();
+        self.known = current;
+
+        Ok(events)
+    }
+}
+
+/// the processes that are currently running
+#[derive(Debug, Default)]
+pub struct Running {
+    processes: HashMap<u32, PathBuf>,
+}
+
+impl Running {
+    /// applies the events to the set of running processes and returns whether
+    /// it changed
+    pub fn apply(&mut self, events: impl IntoIterator<Item = ProcessEvent>) -> bool {
+        events.into_iter().fold(false, |changed, event| {
+            let applied = match event {
+                ProcessEvent::Started { id, exe } => self.processes.insert(id, exe).is_none(),
+                ProcessEvent::Exited { id } => self.processes.remove(&id).is_some(),
+            };
+
+            changed | applied
+        })
+    }
+
+    pub fn exes(&self) -> impl Iterator<Item = &Path> {
+        self.processes.values().map(PathBuf::as_path)
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use {super::*, std::collections::VecDeque};
+
+    /// a process list that reports predetermined events
+    #[derive(Default)]
+    pub struct FakeList {
+        pub events: VecDeque<Vec<ProcessEvent>>,
+    }
+
+    impl ProcessList for FakeList {
+        fn poll(&mut self) -> io::Result<Vec<ProcessEvent>> {
+            Ok(self.events.pop_front().unwrap_or_default())
+        }
+    }
+
+    pub fn started(id: u32, exe: &str) -> ProcessEvent {
+        ProcessEvent::Started {
+            id,
+            exe: exe.into(),
+        }
+    }
+
+    #[test]
+    fn running_tracks_events() {
+        let mut list = FakeList {
+            events: [
+                vec![started(1, "a.exe"), started(2, "b.exe")],
+                vec![ProcessEvent::Exited { id: 1 }],
+                vec![ProcessEvent::Exited { id: 3 }],
+            ]
+            .into(),
+        };
+        let mut running = Running::default();
+
+        assert!(running.apply(list.poll().unwrap()));
+        assert!(running.apply(list.poll().unwrap()));
+        assert_eq!(running.exes().collect::<Vec<_>>(), [Path::new("b.exe")]);
+
+        assert!(!running.apply(list.poll().unwrap()));
+        assert!(!running.apply(list.poll().unwrap()))
+    }
+
+    #[test]
+    fn snapshots_report_current_process() {
+        let events = Snapshots::default().poll().unwrap();
+        let current = std::env::current_exe().unwrap();
+
+        // the path may be spelled differently, e.g. with a short or
+        // differently cased directory
+        assert!(events.iter().any(|event| matches!(
+            event,
+            ProcessEvent::Started { id, exe }
+                if *id == std::process::id() && exe.file_name() == current.file_name()
+        )))
+    }
+}
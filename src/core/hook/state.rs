@@ -1,28 +1,58 @@
 use {
     crate::{
-        cfg::Config,
+        cfg::{Activation, Config},
         core::{
             driver::Driver,
-            hook::{Handler, Process},
+            hook::{
+                held::{self, Input},
+                Applied,
+                Context,
+                Handler,
+                Process,
+                ProcessEvent,
+                Running,
+            },
+            rules::{self, Override},
         },
+        output::report,
+        windows::window::Window,
+    },
+    std::{
+        sync::{Mutex, MutexGuard},
+        time::{Duration, Instant},
     },
-    std::sync::{Mutex, MutexGuard},
 };
 
 pub struct State {
-    pub config: Config,
+    config: Config,
+    /// the inputs entries in the config are interested in
+    watched: Vec<Input>,
     driver: Driver,
     handler: Handler,
     focus: Option<Process>,
+    running: Running,
+    forced: Option<Override>,
+    /// while paused, the handler is only called with an empty context once
+    paused: bool,
+    /// like paused, but ends with the next input
+    idle: bool,
+    /// what the handler applied last
+    applied: Option<Applied>,
 }
 
 impl State {
     pub fn new(config: Config, driver: Driver, handler: Handler) -> State {
         State {
+            watched: held::watched(&config),
             config,
             driver,
             handler,
             focus: None,
+            running: <_>::default(),
+            forced: None,
+            paused: false,
+            idle: false,
+            applied: None,
         }
     }
 
@@ -35,6 +65,18 @@ impl State {
         self.call_handler()
     }
 
+    pub fn focus_id(&self) -> Option<u32> {
+        self.focus.as_ref().map(|proc| proc.id)
+    }
+
+    /// clears the focus if the process with the given id is still focused
+    pub fn focus_exited(&mut self, id: u32) {
+        if self.focus_id() == Some(id) {
+            self.focus = None;
+            self.call_handler()
+        }
+    }
+
     pub fn set_cursor_hidden(&mut self, hidden: bool) {
         if let Some(proc) = &mut self.focus {
             proc.cursor_hidden.replace(hidden);
@@ -42,11 +84,380 @@ impl State {
         }
     }
 
+    /// re-evaluates the current state if the focused window's title changed
+    pub fn title_changed(&mut self, window: Window) {
+        if self
+            .focus
+            .as_ref()
+            .is_some_and(|proc| proc.window == Some(window))
+        {
+            self.call_handler()
+        }
+    }
+
+    pub fn set_config(&mut self, config: Config) {
+        self.watched = held::watched(&config);
+        self.config = config
+    }
+
+    /// changes the config and applies the result
+    pub fn update_config(&mut self, f: impl FnOnce(&mut Config)) {
+        f(&mut self.config);
+        self.watched = held::watched(&self.config);
+        self.call_handler()
+    }
+
+    /// whether the config watches any mouse buttons and keys, respectively
+    pub fn watches_input(&self) -> (bool, bool) {
+        let buttons = |input: &&Input| matches!(input, Input::Button(_));
+
+        (
+            self.watched.iter().any(|input| buttons(&input)),
+            self.watched.iter().any(|input| !buttons(&input)),
+        )
+    }
+
+    /// records that an input was pressed or released in the focused process.
+    /// presses only count if the process's own entry watches the input, so
+    /// inputs in other processes don't call the handler.
+    pub fn set_input(&mut self, input: Input, pressed: bool) {
+        let Some(proc) = &mut self.focus else { return };
+
+        let changed = if pressed {
+            let watched = rules::entry_for(&self.config, proc.exe())
+                .and_then(|entry| entry.while_held.as_ref())
+                .is_some_and(|while_held| {
+                    Input::from_config(&while_held.input).is_ok_and(|watched| watched == input)
+                });
+
+            watched && proc.held.press(input)
+        } else {
+            proc.held.release(input)
+        };
+
+        if changed {
+            self.call_handler()
+        }
+    }
+
+    /// whether the config contains any entries that depend on the set of
+    /// running processes
+    pub fn needs_running(&self) -> bool {
+        self.config
+            .processes
+            .values()
+            .any(|entry| entry.activation == Activation::Running)
+    }
+
+    pub fn update_running(&mut self, events: Vec<ProcessEvent>) {
+        if self.running.apply(events) {
+            self.call_handler()
+        }
+    }
+
+    pub fn set_override(&mut self, forced: Option<Override>) {
+        self.forced = forced;
+        self.call_handler()
+    }
+
+    /// clears the override if it expired
+    pub fn expire_override(&mut self, now: Instant) {
+        if self
+            .forced
+            .as_ref()
+            .is_some_and(|forced| forced.expired(now))
+        {
+            report!(Info, Control, "sensitivity override expired");
+            self.set_override(None)
+        }
+    }
+
+    /// pauses or resumes calling the handler and returns whether that changed
+    /// anything. pausing calls the handler as if nothing was focused or
+    /// running, while resuming re-evaluates the current state.
+    pub fn set_paused(&mut self, paused: bool) -> bool {
+        if self.paused == paused {
+            return false
+        }
+
+        self.paused = paused;
+
+        if paused {
+            self.call_handler_suspended()
+        } else {
+            self.call_handler()
+        }
+
+        true
+    }
+
+    pub fn idle_timeout(&self) -> Option<Duration> {
+        self.config.idle_timeout_secs.map(Duration::from_secs)
+    }
+
+    /// calls the handler as if nothing was focused or running while idle, and
+    /// re-evaluates the current state once no longer idle
+    pub fn set_idle(&mut self, idle: bool) {
+        self.idle = idle;
+
+        if self.paused {
+            return
+        }
+
+        if idle {
+            report!(Info, General, "idle, applying the default sensitivity");
+            self.call_handler_suspended()
+        } else {
+            report!(Info, General, "no longer idle");
+            self.call_handler()
+        }
+    }
+
+    /// re-reads the driver's settings and applies the current state again,
+    /// with the given focus if it's known
+    pub fn refresh(&mut self, focus: Option<Process>) {
+        if let Err(e) = self.driver.refresh() {
+            report!(Warn, Driver, "couldn't read the driver's settings: {e}")
+        }
+
+        if let Some(focus) = focus {
+            self.focus.replace(focus);
+        }
+
+        if self.paused || self.idle {
+            self.call_handler_suspended()
+        } else {
+            self.call_handler()
+        }
+    }
+
+    /// reconnects to the driver if the connection broke and another attempt is
+    /// due
+    pub fn poll_driver(&mut self, now: Instant) {
+        self.driver.poll(now)
+    }
+
+    pub fn paused(&self) -> bool {
+        self.paused
+    }
+
+    /// calls `f` with the same arguments the handler would receive and what
+    /// it applied last, without allowing any changes
+    pub fn query<T>(&self, f: impl FnOnce(&Config, &Driver, &Context, Option<&Applied>) -> T) -> T {
+        let context = Context {
+            focus: self.focus.as_ref(),
+            running: &self.running,
+            forced: self.forced.as_ref(),
+            suspended: false,
+            cursor_hidden: None,
+        };
+
+        f(&self.config, &self.driver, &context, self.applied.as_ref())
+    }
+
+    fn call_handler_suspended(&mut self) {
+        let context = Context {
+            focus: None,
+            running: &<_>::default(),
+            forced: None,
+            suspended: true,
+            cursor_hidden: None,
+        };
+
+        self.applied = Some((self.handler)(&self.config, &mut self.driver, &context))
+    }
+
     fn call_handler(&mut self) {
-        if let Some(proc) = &self.focus {
-            (self.handler)(&self.config, &mut self.driver, proc);
+        if self.paused || self.idle {
+            return
         }
+
+        let context = Context {
+            focus: self.focus.as_ref(),
+            running: &self.running,
+            forced: self.forced.as_ref(),
+            suspended: false,
+            cursor_hidden: None,
+        };
+
+        self.applied = Some((self.handler)(&self.config, &mut self.driver, &context))
     }
 }
 
 static STATE: Mutex<Option<State>> = Mutex::new(None);
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        crate::{
+            cfg::{self, Button, Entry, WhileHeld},
+            msg,
+        },
+        std::{cell::RefCell, path::PathBuf},
+    };
+
+    thread_local! {
+        static FOCUSED: RefCell<Vec<Option<PathBuf>>> = <_>::default();
+        static APPLIED: RefCell<Vec<f64>> = <_>::default();
+    }
+
+    fn record(_: &Config, _: &mut Driver, context: &Context) -> Applied {
+        let exe = context.focus.map(|process| process.exe().to_owned());
+        FOCUSED.with(|focused| focused.borrow_mut().push(exe.clone()));
+
+        Applied {
+            source: match exe {
+                Some(_) => msg::Source::Entry,
+                None => msg::Source::Default,
+            },
+            entry: exe,
+        }
+    }
+
+    fn record_sensitivity(config: &Config, _: &mut Driver, context: &Context) -> Applied {
+        let sensitivity = rules::resolve(config, context).sensitivity();
+        APPLIED.with(|applied| applied.borrow_mut().push(sensitivity));
+
+        Applied {
+            entry: None,
+            source: msg::Source::Default,
+        }
+    }
+
+    #[test]
+    fn pause_and_resume() {
+        let mut state = State::new(<_>::default(), Driver::dummy().unwrap(), record);
+        state.set_focus(Process::new(1, "game.exe".into()));
+
+        assert!(state.set_paused(true));
+        assert!(!state.set_paused(true));
+        state.set_focus(Process::new(2, "other.exe".into()));
+        state.set_cursor_hidden(true);
+
+        assert!(state.paused());
+        assert_eq!(FOCUSED.with(RefCell::take), [Some("game.exe".into()), None]);
+
+        // what was applied while paused is reported, not the focus
+        let applied = |state: &State| state.query(|_, _, _, applied| applied.cloned());
+        assert_eq!(
+            applied(&state),
+            Some(Applied {
+                entry: None,
+                source: msg::Source::Default
+            })
+        );
+
+        assert!(state.set_paused(false));
+        assert_eq!(FOCUSED.with(RefCell::take), [Some("other.exe".into())]);
+        assert_eq!(
+            applied(&state),
+            Some(Applied {
+                entry: Some("other.exe".into()),
+                source: msg::Source::Entry
+            })
+        )
+    }
+
+    #[test]
+    fn held_inputs() {
+        let mut config = Config::default();
+        config.processes.insert("game.exe".into(), Entry {
+            sensitivity: 2.,
+            while_held: Some(WhileHeld {
+                input: cfg::Input::Button(Button::Right),
+                sensitivity: 0.5,
+            }),
+            ..<_>::default()
+        });
+
+        let mut state = State::new(config, Driver::dummy().unwrap(), record_sensitivity);
+        let right = Input::Button(Button::Right);
+
+        state.set_focus(Process::new(1, "game.exe".into()));
+        // not watched
+        state.set_input(Input::Button(Button::Left), true);
+        state.set_input(right, true);
+        // repeated
+        state.set_input(right, true);
+        assert_eq!(APPLIED.with(RefCell::take), [2., 0.5]);
+
+        // losing focus while held forgets the input, so it has to be pressed
+        // again once focused
+        state.set_focus(Process::new(2, "other.exe".into()));
+        state.set_input(right, false);
+        // not watched by the entry for the focused process
+        state.set_input(right, true);
+        state.set_input(right, false);
+        state.set_focus(Process::new(1, "game.exe".into()));
+        assert_eq!(APPLIED.with(RefCell::take), [1., 2.]);
+
+        state.set_input(right, true);
+        state.set_input(right, false);
+        assert_eq!(APPLIED.with(RefCell::take), [0.5, 2.])
+    }
+
+    #[test]
+    fn title_changes() {
+        let mut state = State::new(<_>::default(), Driver::dummy().unwrap(), record);
+        let window = |handle: usize| Window::new(handle as _);
+
+        let mut process = Process::new(1, "game.exe".into());
+        process.window = Some(window(0x10));
+        state.set_focus(process);
+
+        // another window's title changed
+        state.title_changed(window(0x20));
+        state.title_changed(window(0x10));
+
+        assert_eq!(FOCUSED.with(RefCell::take), [
+            Some("game.exe".into()),
+            Some("game.exe".into())
+        ])
+    }
+
+    #[test]
+    fn idle() {
+        let mut state = State::new(<_>::default(), Driver::dummy().unwrap(), record);
+        state.set_focus(Process::new(1, "game.exe".into()));
+
+        state.set_idle(true);
+        state.set_focus(Process::new(2, "other.exe".into()));
+        assert_eq!(FOCUSED.with(RefCell::take), [Some("game.exe".into()), None]);
+
+        state.set_idle(false);
+        assert_eq!(FOCUSED.with(RefCell::take), [Some("other.exe".into())]);
+
+        // pausing takes precedence
+        state.set_paused(true);
+        state.set_idle(true);
+        state.set_idle(false);
+        assert_eq!(FOCUSED.with(RefCell::take), [None]);
+
+        state.set_idle(true);
+        state.set_paused(false);
+        assert!(FOCUSED.with(RefCell::take).is_empty())
+    }
+
+    #[test]
+    fn refresh() {
+        let mut state = State::new(<_>::default(), Driver::dummy().unwrap(), record);
+        state.set_focus(Process::new(1, "game.exe".into()));
+
+        // applied again even though nothing changed
+        state.refresh(None);
+        state.refresh(Some(Process::new(2, "other.exe".into())));
+        assert_eq!(FOCUSED.with(RefCell::take), [
+            Some("game.exe".into()),
+            Some("game.exe".into()),
+            Some("other.exe".into())
+        ]);
+
+        state.set_paused(true);
+        state.refresh(Some(Process::new(1, "game.exe".into())));
+        assert_eq!(FOCUSED.with(RefCell::take), [None, None]);
+
+        state.set_paused(false);
+        assert_eq!(FOCUSED.with(RefCell::take), [Some("game.exe".into())])
+    }
+}
@@ -0,0 +1,259 @@
+//! session and power notifications, after which the driver's settings and the
+//! focus may not match the state anymore
+
+use {
+    crate::{output::report, windows::util::validate},
+    std::{
+        cell::RefCell,
+        fmt,
+        io,
+        mem::{self, MaybeUninit},
+        ptr,
+        sync::mpsc::{self, Receiver, Sender},
+        thread,
+    },
+    winapi::{
+        shared::{
+            minwindef::{BOOL, DWORD, LPARAM, LRESULT, WPARAM},
+            windef::HWND,
+        },
+        um::{
+            libloaderapi::GetModuleHandleA,
+            winuser::{
+                CreateWindowExA,
+                DefWindowProcA,
+                DispatchMessageA,
+                GetMessageA,
+                RegisterClassA,
+                TranslateMessage,
+                PBT_APMRESUMEAUTOMATIC,
+                PBT_APMSUSPEND,
+                WM_POWERBROADCAST,
+                WM_WTSSESSION_CHANGE,
+                WNDCLASSA,
+                WTS_SESSION_LOCK,
+                WTS_SESSION_UNLOCK,
+            },
+        },
+    },
+};
+
+const CLASS_NAME: &[u8] = b"senscale_notifications\0";
+
+const NOTIFY_FOR_THIS_SESSION: DWORD = 0;
+
+// not part of winapi
+#[link(name = "wtsapi32")]
+extern "system" {
+    fn WTSRegisterSessionNotification(window: HWND, flags: DWORD) -> BOOL;
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SystemEvent {
+    Locked,
+    Unlocked,
+    Suspended,
+    Resumed,
+}
+
+impl SystemEvent {
+    /// the event a window message is about, if any
+    pub fn from_message(message: u32, param: WPARAM) -> Option<SystemEvent> {
+        match (message, param) {
+            (WM_WTSSESSION_CHANGE, WTS_SESSION_LOCK) => Some(SystemEvent::Locked),
+            (WM_WTSSESSION_CHANGE, WTS_SESSION_UNLOCK) => Some(SystemEvent::Unlocked),
+            (WM_POWERBROADCAST, PBT_APMSUSPEND) => Some(SystemEvent::Suspended),
+            // sent on every resume, unlike PBT_APMRESUMESUSPEND
+            (WM_POWERBROADCAST, PBT_APMRESUMEAUTOMATIC) => Some(SystemEvent::Resumed),
+            _ => None,
+        }
+    }
+
+    /// whether the current state has to be applied again afterwards
+    pub fn reapplies(self) -> bool {
+        matches!(self, SystemEvent::Unlocked | SystemEvent::Resumed)
+    }
+}
+
+impl fmt::Display for SystemEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SystemEvent::Locked => "session locked",
+            SystemEvent::Unlocked => "session unlocked",
+            SystemEvent::Suspended => "system suspended",
+            SystemEvent::Resumed => "system resumed",
+        }
+        .fmt(f)
+    }
+}
+
+/// a source of system events
+pub trait SystemEvents {
+    /// waits for the next event, returning `None` once there are no more
+    fn next(&mut self) -> Option<SystemEvent>;
+}
+
+/// reports each event and calls `refresh` after the ones that require the
+/// current state to be applied again, until there are no more events or it
+/// returns `false`
+pub fn forward(events: &mut impl SystemEvents, mut refresh: impl FnMut(SystemEvent) -> bool) {
+    while let Some(event) = events.next() {
+        report!(Info, General, "{event}");
+
+        if event.reapplies() && !refresh(event) {
+            break
+        }
+    }
+}
+
+thread_local! {
+    static SENDER: RefCell<Option<Sender<SystemEvent>>> = <_>::default();
+}
+
+/// the notifications received by a hidden window on a thread of its own
+pub struct Notifications {
+    receiver: Receiver<SystemEvent>,
+}
+
+impl Notifications {
+    pub fn register() -> io::Result<Notifications> {
+        let (sender, receiver) = mpsc::channel();
+        let (created, creation) = mpsc::channel();
+
+        thread::spawn(move || {
+            SENDER.with(|cell| cell.replace(Some(sender)));
+
+            let res = unsafe { create_window() };
+            let ok = res.is_ok();
+            created.send(res).ok();
+
+            if ok {
+                pump()
+            }
+        });
+
+        creation
+            .recv()
+            .map_err(|_| io::Error::other("notification thread exited"))??;
+
+        Ok(Notifications { receiver })
+    }
+}
+
+impl SystemEvents for Notifications {
+    fn next(&mut self) -> Option<SystemEvent> {
+        self.receiver.recv().ok()
+    }
+}
+
+/// creates a hidden top-level window, since message-only windows don't receive
+/// power broadcasts
+unsafe fn create_window() -> io::Result<()> {
+    let instance = GetModuleHandleA(ptr::null());
+
+    let class = WNDCLASSA {
+        lpfnWndProc: Some(window_proc),
+        hInstance: instance,
+        lpszClassName: CLASS_NAME.as_ptr().cast(),
+        ..mem::zeroed()
+    };
+
+    if RegisterClassA(&class) == 0 {
+        return Err(io::Error::last_os_error())
+    }
+
+    let window = CreateWindowExA(
+        0,
+        CLASS_NAME.as_ptr().cast(),
+        CLASS_NAME.as_ptr().cast(),
+        0,
+        0,
+        0,
+        0,
+        0,
+        ptr::null_mut(),
+        ptr::null_mut(),
+        instance,
+        ptr::null_mut(),
+    );
+
+    if window.is_null() {
+        return Err(io::Error::last_os_error())
+    }
+
+    validate(WTSRegisterSessionNotification(
+        window,
+        NOTIFY_FOR_THIS_SESSION,
+    ))
+}
+
+fn pump() {
+    let mut msg = MaybeUninit::uninit();
+
+    while unsafe { GetMessageA(msg.as_mut_ptr(), ptr::null_mut(), 0, 0) } > 0 {
+        unsafe {
+            TranslateMessage(msg.as_ptr());
+            DispatchMessageA(msg.as_ptr());
+        }
+    }
+}
+
+unsafe extern "system" fn window_proc(
+    window: HWND,
+    message: u32,
+    param: WPARAM,
+    data: LPARAM,
+) -> LRESULT {
+    if let Some(event) = SystemEvent::from_message(message, param) {
+        SENDER.with(|cell| {
+            if let Some(sender) = &*cell.borrow() {
+                sender.send(event).ok();
+            }
+        })
+    }
+
+    DefWindowProcA(window, message, param, data)
+}
+
+#[cfg(test)]
+mod test {
+    use {super::*, std::collections::VecDeque, winapi::um::winuser::WM_PAINT};
+
+    struct FakeEvents(VecDeque<SystemEvent>);
+
+    impl SystemEvents for FakeEvents {
+        fn next(&mut self) -> Option<SystemEvent> {
+            self.0.pop_front()
+        }
+    }
+
+    #[test]
+    fn from_message() {
+        assert_eq!(
+            SystemEvent::from_message(WM_WTSSESSION_CHANGE, WTS_SESSION_UNLOCK),
+            Some(SystemEvent::Unlocked)
+        );
+        assert_eq!(
+            SystemEvent::from_message(WM_POWERBROADCAST, PBT_APMSUSPEND),
+            Some(SystemEvent::Suspended)
+        );
+        assert_eq!(SystemEvent::from_message(WM_PAINT, 0), None)
+    }
+
+    #[test]
+    fn forward_reapplying() {
+        use SystemEvent::*;
+
+        let mut events = FakeEvents([Locked, Unlocked, Suspended, Resumed, Unlocked].into());
+        let mut refreshed = Vec::new();
+
+        // stops once the state is gone
+        forward(&mut events, |event| {
+            refreshed.push(event);
+            refreshed.len() < 2
+        });
+
+        assert_eq!(refreshed, [Unlocked, Resumed]);
+        assert_eq!(events.0, [Unlocked])
+    }
+}
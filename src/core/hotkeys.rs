@@ -0,0 +1,512 @@
+//! global hotkeys, which are registered for the thread that receives requests
+
+use {
+    crate::{
+        cfg::{Action, Config},
+        core::hook::Context,
+        output::report,
+        windows::util::validate,
+    },
+    std::{
+        fmt,
+        path::{Path, PathBuf},
+        ptr,
+        str::FromStr,
+    },
+    winapi::um::winuser::{
+        RegisterHotKey,
+        UnregisterHotKey,
+        MOD_ALT,
+        MOD_CONTROL,
+        MOD_NOREPEAT,
+        MOD_SHIFT,
+        MOD_WIN,
+        VK_DELETE,
+        VK_DOWN,
+        VK_END,
+        VK_ESCAPE,
+        VK_F1,
+        VK_HOME,
+        VK_INSERT,
+        VK_LEFT,
+        VK_NEXT,
+        VK_OEM_MINUS,
+        VK_OEM_PLUS,
+        VK_PRIOR,
+        VK_RETURN,
+        VK_RIGHT,
+        VK_SPACE,
+        VK_TAB,
+        VK_UP,
+    },
+};
+
+/// the lowest sensitivity a nudge results in
+const MIN_SENSITIVITY: f64 = 0.01;
+
+/// a key combination, like `Ctrl+Alt+Up`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Hotkey {
+    pub ctrl: bool,
+    pub alt: bool,
+    pub shift: bool,
+    pub win: bool,
+    pub key: Key,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Key {
+    /// a letter or digit
+    Char(char),
+    /// a function key, from 1 to 24
+    F(u8),
+    Up,
+    Down,
+    Left,
+    Right,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    Insert,
+    Delete,
+    Space,
+    Tab,
+    Enter,
+    Escape,
+    Plus,
+    Minus,
+}
+
+const NAMED_KEYS: [(&str, Key); 16] = [
+    ("Up", Key::Up),
+    ("Down", Key::Down),
+    ("Left", Key::Left),
+    ("Right", Key::Right),
+    ("Home", Key::Home),
+    ("End", Key::End),
+    ("PageUp", Key::PageUp),
+    ("PageDown", Key::PageDown),
+    ("Insert", Key::Insert),
+    ("Delete", Key::Delete),
+    ("Space", Key::Space),
+    ("Tab", Key::Tab),
+    ("Enter", Key::Enter),
+    ("Escape", Key::Escape),
+    ("Plus", Key::Plus),
+    ("Minus", Key::Minus),
+];
+
+impl FromStr for Key {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut chars = s.chars();
+
+        if let (Some(c), None) = (chars.next(), chars.next()) {
+            if c.is_ascii_alphanumeric() {
+                return Ok(Key::Char(c.to_ascii_uppercase()))
+            }
+        }
+
+        let function = s
+            .strip_prefix(['F', 'f'])
+            .and_then(|n| n.parse().ok())
+            .filter(|n| (1..=24).contains(n));
+
+        if let Some(n) = function {
+            return Ok(Key::F(n))
+        }
+
+        NAMED_KEYS
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(s))
+            .map(|&(_, key)| key)
+            .ok_or_else(|| format!("unknown key {s}"))
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Key::Char(c) => c.fmt(f),
+            Key::F(n) => write!(f, "F{n}"),
+            key => {
+                let (name, _) = NAMED_KEYS
+                    .iter()
+                    .find(|(_, named)| named == key)
+                    .expect("all other keys are named");
+
+                name.fmt(f)
+            }
+        }
+    }
+}
+
+impl Key {
+    /// the virtual-key code
+    pub fn code(self) -> u32 {
+        (match self {
+            Key::Char(c) => c as i32,
+            Key::F(n) => VK_F1 + n as i32 - 1,
+            Key::Up => VK_UP,
+            Key::Down => VK_DOWN,
+            Key::Left => VK_LEFT,
+            Key::Right => VK_RIGHT,
+            Key::Home => VK_HOME,
+            Key::End => VK_END,
+            Key::PageUp => VK_PRIOR,
+            Key::PageDown => VK_NEXT,
+            Key::Insert => VK_INSERT,
+            Key::Delete => VK_DELETE,
+            Key::Space => VK_SPACE,
+            Key::Tab => VK_TAB,
+            Key::Enter => VK_RETURN,
+            Key::Escape => VK_ESCAPE,
+            Key::Plus => VK_OEM_PLUS,
+            Key::Minus => VK_OEM_MINUS,
+        }) as u32
+    }
+}
+
+impl FromStr for Hotkey {
+    type Err = String;
+
+    /// parses keys separated by `+`, where all but the last one have to be
+    /// modifiers
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (modifiers, key) = s.rsplit_once('+').unwrap_or(("", s));
+        let mut hotkey = Hotkey {
+            ctrl: false,
+            alt: false,
+            shift: false,
+            win: false,
+            key: key.trim().parse()?,
+        };
+
+        for modifier in modifiers.split('+').filter(|modifier| !modifier.is_empty()) {
+            let flag = match modifier.to_ascii_lowercase().as_str() {
+                "ctrl" | "control" => &mut hotkey.ctrl,
+                "alt" => &mut hotkey.alt,
+                "shift" => &mut hotkey.shift,
+                "win" => &mut hotkey.win,
+                _ => return Err(format!("unknown modifier {modifier}")),
+            };
+
+            if *flag {
+                return Err(format!("{modifier} is given more than once"))
+            }
+
+            *flag = true
+        }
+
+        Ok(hotkey)
+    }
+}
+
+impl fmt::Display for Hotkey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let modifiers = [
+            (self.ctrl, "Ctrl"),
+            (self.alt, "Alt"),
+            (self.shift, "Shift"),
+            (self.win, "Win"),
+        ];
+
+        for (_, name) in modifiers.iter().filter(|(set, _)| *set) {
+            write!(f, "{name}+")?
+        }
+
+        self.key.fmt(f)
+    }
+}
+
+impl Hotkey {
+    fn modifiers(self) -> isize {
+        [
+            (self.ctrl, MOD_CONTROL),
+            (self.alt, MOD_ALT),
+            (self.shift, MOD_SHIFT),
+            (self.win, MOD_WIN),
+        ]
+        .into_iter()
+        .filter(|&(set, _)| set)
+        .fold(MOD_NOREPEAT, |modifiers, (_, flag)| modifiers | flag)
+    }
+}
+
+/// what should be done in response to a hotkey
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    /// sets the sensitivity of the entry with the given key
+    Nudge {
+        key: PathBuf,
+        sensitivity: f64,
+        persist: bool,
+    },
+    Pause,
+    Resume,
+}
+
+/// determines what `action` should do given the current state, or why it
+/// can't do anything
+pub fn dispatch(
+    action: &Action,
+    config: &Config,
+    context: &Context,
+    paused: bool,
+) -> Result<Command, String> {
+    let (step, persist) = match *action {
+        Action::TogglePause if paused => return Ok(Command::Resume),
+        Action::TogglePause => return Ok(Command::Pause),
+        Action::Nudge { step, persist } => (step, persist),
+    };
+
+    let resolution = super::resolve(config, context);
+
+    // the nudged entry wouldn't be applied anyway
+    if resolution.forced.is_some() {
+        return Err("the sensitivity is overridden".into())
+    }
+
+    if resolution.scripted.is_some() {
+        return Err("the sensitivity is set by the script".into())
+    }
+
+    // the applied entry is nudged, or a new one is added for the focused
+    // process if it doesn't have one yet, starting from the applied fallback
+    let (key, sensitivity) = match (resolution.selected(), context.focus) {
+        (Some(rule), _) => (rule.key.to_owned(), rule.entry.sensitivity),
+        (None, Some(focus)) => {
+            let name = focus
+                .exe()
+                .file_name()
+                .ok_or("the focused process is unknown")?;
+
+            if config.processes.contains_key(Path::new(name)) {
+                return Err(format!(
+                    "the entry for {} isn't applied",
+                    name.to_string_lossy()
+                ))
+            }
+
+            (name.into(), resolution.sensitivity())
+        }
+        (None, None) => return Err("no process is focused".into()),
+    };
+
+    // rounded so repeated nudges don't accumulate errors
+    let sensitivity = ((sensitivity + step) * 1000.).round() / 1000.;
+
+    Ok(Command::Nudge {
+        key,
+        sensitivity: sensitivity.max(MIN_SENSITIVITY),
+        persist,
+    })
+}
+
+/// hotkeys registered for the current thread, which are unregistered when
+/// dropped. their ids are their indices.
+pub struct Registered {
+    actions: Vec<Option<Action>>,
+}
+
+impl Registered {
+    /// registers the hotkeys in the config, reporting the ones that can't be
+    /// used
+    pub fn register(config: &Config) -> Registered {
+        let actions = config
+            .hotkeys
+            .iter()
+            .enumerate()
+            .map(|(id, (keys, action))| {
+                let registered = keys.parse::<Hotkey>().and_then(|hotkey| {
+                    validate(unsafe {
+                        RegisterHotKey(
+                            ptr::null_mut(),
+                            id as i32,
+                            hotkey.modifiers() as u32,
+                            hotkey.key.code(),
+                        )
+                    })
+                    .map_err(|e| format!("{hotkey}: {e}"))
+                });
+
+                match registered {
+                    Ok(()) => Some(action.clone()),
+                    Err(e) => {
+                        report!(Warn, Config, "couldn't register hotkey {keys}: {e}");
+                        None
+                    }
+                }
+            })
+            .collect();
+
+        Registered { actions }
+    }
+
+    /// replaces the hotkeys with the ones in `config`
+    pub fn reregister(&mut self, config: &Config) {
+        // unregistered first, so the same hotkeys can be registered again
+        self.unregister();
+        *self = Registered::register(config)
+    }
+
+    pub fn action(&self, id: i32) -> Option<&Action> {
+        self.actions.get(id as usize)?.as_ref()
+    }
+
+    fn unregister(&mut self) {
+        for (id, action) in self.actions.drain(..).enumerate() {
+            if action.is_some() {
+                unsafe { UnregisterHotKey(ptr::null_mut(), id as i32) };
+            }
+        }
+    }
+}
+
+impl Drop for Registered {
+    fn drop(&mut self) {
+        self.unregister()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        crate::{
+            cfg::Entry,
+            core::{
+                hook::{Process, Running},
+                rules::Override,
+            },
+        },
+    };
+
+    #[test]
+    fn parse_hotkeys() {
+        let parse = |s: &str| s.parse::<Hotkey>().map(|hotkey| hotkey.to_string());
+
+        assert_eq!(parse("Ctrl+Alt+Up"), Ok("Ctrl+Alt+Up".into()));
+        assert_eq!(parse("alt+control+p"), Ok("Ctrl+Alt+P".into()));
+        assert_eq!(parse("Win+Shift+f12"), Ok("Shift+Win+F12".into()));
+        assert_eq!(parse("pagedown"), Ok("PageDown".into()));
+        assert_eq!(
+            parse("Ctrl+Ctrl+A"),
+            Err("Ctrl is given more than once".into())
+        );
+        assert_eq!(parse("Hyper+A"), Err("unknown modifier Hyper".into()));
+        assert_eq!(parse("Ctrl+Alt"), Err("unknown key Alt".into()));
+        assert_eq!(parse("Ctrl+F25"), Err("unknown key F25".into()));
+        assert_eq!(parse("Ctrl+"), Err("unknown key ".into()));
+        assert_eq!(
+            "Ctrl+Alt+1".parse::<Hotkey>().unwrap().key.code(),
+            '1' as u32
+        )
+    }
+
+    #[test]
+    fn dispatch_actions() {
+        let mut config = Config::default();
+        config.processes.insert("game.exe".into(), Entry {
+            sensitivity: 2.,
+            ..<_>::default()
+        });
+        config.processes.insert("paint.exe".into(), Entry {
+            sensitivity: 3.,
+            only_if_cursor_hidden: true,
+            ..<_>::default()
+        });
+
+        let running = Running::default();
+        let dispatch = |action: &Action, exe: Option<&str>| {
+            let focus = exe.map(|exe| {
+                let mut process = Process::new(0, exe.into());
+                process.cursor_hidden = Some(false);
+                process
+            });
+            let context = Context {
+                focus: focus.as_ref(),
+                running: &running,
+                forced: None,
+                suspended: false,
+                cursor_hidden: None,
+            };
+
+            dispatch(action, &config, &context, false)
+        };
+        let nudge = |step| Action::Nudge {
+            step,
+            persist: false,
+        };
+
+        assert_eq!(dispatch(&Action::TogglePause, None), Ok(Command::Pause));
+        assert_eq!(
+            dispatch(&nudge(0.1), Some(r"C:\game.exe")),
+            Ok(Command::Nudge {
+                key: "game.exe".into(),
+                sensitivity: 2.1,
+                persist: false
+            })
+        );
+        assert_eq!(
+            dispatch(&nudge(-5.), Some(r"C:\game.exe")),
+            Ok(Command::Nudge {
+                key: "game.exe".into(),
+                sensitivity: MIN_SENSITIVITY,
+                persist: false
+            })
+        );
+        assert_eq!(
+            dispatch(&nudge(0.25), Some(r"C:\other.exe")),
+            Ok(Command::Nudge {
+                key: "other.exe".into(),
+                sensitivity: 1.25,
+                persist: false
+            })
+        );
+        assert_eq!(
+            dispatch(&nudge(0.1), Some(r"C:\paint.exe")),
+            Err("the entry for paint.exe isn't applied".into())
+        );
+        assert_eq!(
+            dispatch(&nudge(0.1), None),
+            Err("no process is focused".into())
+        );
+
+        config.unmatched = Some(3.);
+        let other = Process::new(0, r"C:\other.exe".into());
+        let context = Context {
+            focus: Some(&other),
+            running: &running,
+            forced: None,
+            suspended: false,
+            cursor_hidden: None,
+        };
+
+        assert_eq!(
+            super::dispatch(&nudge(0.1), &config, &context, false),
+            Ok(Command::Nudge {
+                key: "other.exe".into(),
+                sensitivity: 3.1,
+                persist: false
+            })
+        );
+
+        let forced = Override {
+            sensitivity: 2.,
+            process: None,
+            expires: None,
+        };
+        let context = Context {
+            forced: Some(&forced),
+            ..context
+        };
+
+        assert_eq!(
+            super::dispatch(&nudge(0.1), &config, &context, false),
+            Err("the sensitivity is overridden".into())
+        )
+    }
+}
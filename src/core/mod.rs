@@ -1,108 +1,504 @@
 use {
     crate::{
         cfg::{self, Config},
+        instance,
+        log,
         msg,
-        thread_id::ThreadId,
-        windows::thread,
+        output::{self, report},
+        windows::window::Window,
     },
     driver::Driver,
-    hook::Hooks,
-    std::{io, path::Path},
+    hook::{Context, Handles, Hooks, Notifications, Process, Snapshots, SystemClock},
+    rules::Override,
+    std::{
+        fs,
+        io,
+        path::PathBuf,
+        time::{Duration, Instant, SystemTime},
+    },
 };
 
+mod commands;
 mod cursor;
 mod driver;
+mod explain;
 mod hook;
+mod hotkeys;
+mod rules;
+mod script;
 
-pub fn run(parent_thread: Option<ThreadId>) -> io::Result<()> {
-    let Init { config, driver } = match parent_thread {
-        Some(thread) => {
-            thread.send(msg::Client::Running {
-                msg_thread: thread::current_id(),
-            })?;
-
-            match init() {
-                Ok(init) => {
-                    thread.send(msg::Client::Printed)?;
-                    init
-                }
-                Err(e) => {
-                    eprint!("initialization error: {e}");
-                    return thread.send(msg::Client::Printed)
+pub use explain::explain;
+
+const PROCESS_POLL_INTERVAL: Duration = Duration::from_secs(1);
+const EXIT_WAIT_TIMEOUT: Duration = Duration::from_millis(250);
+const OVERRIDE_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+const IDLE_CHECK_INTERVAL: Duration = Duration::from_millis(500);
+const DRIVER_POLL_INTERVAL: Duration = Duration::from_millis(250);
+const HTTP_REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub fn run(output: Option<PathBuf>, verbose: bool) -> io::Result<()> {
+    let started = Instant::now();
+    let _lock = instance::Lock::acquire(instance::lock_file())?
+        .ok_or_else(|| io::Error::new(io::ErrorKind::AlreadyExists, "already running"))?;
+
+    // read before anything is reported, so it can be written to the rotated
+    // files
+    let config = cfg::read_config();
+    let rotation = config
+        .as_ref()
+        .map(|config| config.logs)
+        .unwrap_or_default()
+        .rotation();
+    output::set_level(output_level(config.as_ref().ok(), verbose));
+
+    if let Some(path) = output {
+        output::output_to(log::RotatingFile::open(path, rotation)?)
+    }
+
+    output::log_to(log::RotatingFile::open(log::log_file(), rotation)?);
+
+    let Init { config, driver } = init(config)?;
+    load_script(&config);
+    let http = config.http;
+    let mut loaded = SystemTime::now();
+
+    // written before listening, so it exists once clients can connect
+    instance::Record::current(SystemTime::now())?.write(instance::record_file())?;
+    let requests = msg::listen(&msg::default_name()?)?;
+
+    if let Some(http) = http {
+        let submitter = requests.submitter();
+        let serve = msg::http::serve(http.port, move |msg| {
+            submitter.submit(msg, HTTP_REQUEST_TIMEOUT)
+        });
+
+        match serve {
+            Ok(addr) => report!(Info, General, "serving HTTP on {addr}"),
+            Err(e) => report!(Error, General, "couldn't serve HTTP: {e}"),
+        }
+    }
+
+    let mut hook = Hooks::set(config, driver, on_focus_changed).expect("hooks already set");
+    hook::watch_processes(Snapshots::default(), PROCESS_POLL_INTERVAL);
+    hook::watch_focus(Handles::default(), EXIT_WAIT_TIMEOUT);
+    hook::watch_override(OVERRIDE_CHECK_INTERVAL);
+    hook::watch_idle(SystemClock, IDLE_CHECK_INTERVAL);
+    hook::watch_driver(DRIVER_POLL_INTERVAL);
+
+    match Notifications::register() {
+        Ok(notifications) => hook::watch_system(notifications),
+        Err(e) => report!(
+            Error,
+            General,
+            "couldn't register for session and power notifications: {e}"
+        ),
+    }
+
+    // registered on this thread, since that's where they're received
+    let mut registered = hook
+        .query(|config, _, _, _| hotkeys::Registered::register(config))
+        .expect("hooks were just set");
+
+    for incoming in requests.iter() {
+        let request = match incoming {
+            msg::Incoming::Request(request) => request,
+            msg::Incoming::Hotkey(id) => {
+                if let Some(action) = registered.action(id) {
+                    on_hotkey(&mut hook, action)
                 }
+
+                continue
             }
+        };
+
+        if request.msg == msg::Server::Subscribe {
+            request.subscribe();
+            continue
         }
-        None => init()?,
-    };
 
-    let mut hook = Hooks::set(config, driver, on_focus_changed).expect("hooks already set");
+        let stop = request.msg == msg::Server::Stop;
 
-    for msg in msg::iter() {
-        match msg {
-            msg::Server::Stop => break,
-            msg::Server::Reload { params } => {
+        // requests that don't have a response of their own just receive the
+        // output they produced
+        let (response, output) = output::capture(|| match request.msg.clone() {
+            msg::Server::Stop => Some(msg::Client::Stopped),
+            msg::Server::Reload { print } => {
                 let config = read_config();
+                output::set_level(output_level(Some(&config), verbose));
+                load_script(&config);
 
-                if params.print {
+                if print {
                     print_config(&config)
                 }
 
+                registered.reregister(&config);
+
                 hook.set_config(config);
-                params.thread.send(msg::Client::Printed)?
+                loaded = SystemTime::now();
+                msg::publish(msg::Event::ConfigReloaded);
+                None
+            }
+            msg::Server::Set {
+                sensitivity,
+                process,
+                duration_ms,
+            } => match msg::valid_sensitivity(sensitivity) {
+                Err(message) => Some(msg::Client::Error { message }),
+                Ok(sensitivity) => {
+                    let duration = duration_ms.map(Duration::from_millis);
+                    let mut text = format!("overriding sensitivity with {sensitivity}");
+
+                    if let Some(process) = &process {
+                        text += &format!(" for {}", process.display())
+                    }
+
+                    if let Some(duration) = duration {
+                        text += &format!(" for {}", msg::HumanDuration(duration))
+                    }
+
+                    report!(Info, Control, "{text}");
+
+                    hook.set_override(Some(Override {
+                        sensitivity,
+                        process,
+                        // durations too long to represent never expire
+                        expires: duration.and_then(|duration| Instant::now().checked_add(duration)),
+                    }));
+                    None
+                }
+            },
+            msg::Server::ClearOverride => {
+                report!(Info, Control, "cleared sensitivity override");
+                hook.set_override(None);
+                None
+            }
+            msg::Server::Ping => Some(msg::Client::Pong),
+            msg::Server::Pause => {
+                if hook.set_paused(true) {
+                    report!(Info, Control, "paused scaling")
+                } else {
+                    report!(Info, Control, "already paused")
+                }
+
+                None
+            }
+            msg::Server::Resume => {
+                if hook.set_paused(false) {
+                    report!(Info, Control, "resumed scaling")
+                } else {
+                    report!(Info, Control, "not paused")
+                }
+
+                None
+            }
+            msg::Server::TakeOutput => Some(msg::Client::Done {
+                output: output::take_pending(),
+            }),
+            msg::Server::Subscribe => unreachable!("subscriptions are accepted above"),
+            msg::Server::Status => {
+                let paused = hook.paused();
+                let status = hook.query(|_, driver, context, applied| msg::Status {
+                    paused,
+                    focus: context.focus.map(|process| process.exe().to_owned()),
+                    cursor_hidden: context
+                        .focus
+                        .map_or_else(cursor::hidden, Process::cursor_hidden),
+                    rule: applied.and_then(|applied| applied.entry.clone()),
+                    source: applied.map(|applied| applied.source),
+                    sensitivity: driver.sensitivity(),
+                    config_path: cfg::config_dir().ok().map(|dir| dir.file()),
+                    config_loaded_at: msg::unix_secs(loaded),
+                    uptime_secs: started.elapsed().as_secs(),
+                    driver_error: driver.error().map(<_>::to_owned),
+                    forced: context.forced.map(|forced| msg::OverrideStatus {
+                        sensitivity: forced.sensitivity,
+                        process: forced.process.clone(),
+                        expires_in_secs: forced.expires.map(|expires| {
+                            expires.saturating_duration_since(Instant::now()).as_secs()
+                        }),
+                    }),
+                });
+
+                Some(match status {
+                    Some(status) => msg::Client::Status(status),
+                    None => msg::Client::Error {
+                        message: "hooks were removed".into(),
+                    },
+                })
             }
+        });
+
+        request.reply(response.unwrap_or(msg::Client::Done { output }));
+
+        if stop {
+            break
         }
     }
 
+    let _ = fs::remove_file(instance::record_file());
+
     Ok(())
 }
 
+fn on_hotkey(hook: &mut Hooks, action: &cfg::Action) {
+    let paused = hook.paused();
+    let command =
+        hook.query(|config, _, context, _| hotkeys::dispatch(action, config, context, paused));
+
+    match command {
+        Some(Ok(hotkeys::Command::Pause)) => {
+            hook.set_paused(true);
+            report!(Info, Control, "paused scaling")
+        }
+        Some(Ok(hotkeys::Command::Resume)) => {
+            hook.set_paused(false);
+            report!(Info, Control, "resumed scaling")
+        }
+        Some(Ok(hotkeys::Command::Nudge {
+            key,
+            sensitivity,
+            persist,
+        })) => {
+            report!(
+                Info,
+                Control,
+                "nudged the sensitivity for {} to {sensitivity}",
+                key.display()
+            );
+
+            if persist {
+                let saved = cfg::config_dir()
+                    .and_then(|dir| cfg::set_sensitivity(dir.file(), key.clone(), sensitivity));
+
+                if let Err(e) = saved {
+                    report!(Error, Config, "couldn't save the nudged sensitivity: {e}")
+                }
+            }
+
+            hook.update_config(|config| {
+                config.processes.entry(key).or_default().sensitivity = sensitivity
+            })
+        }
+        Some(Err(e)) => report!(Info, Control, "nothing to nudge, {e}"),
+        None => {}
+    }
+}
+
+fn output_level(config: Option<&Config>, verbose: bool) -> log::Level {
+    if verbose {
+        log::Level::Debug
+    } else {
+        config.map(|config| config.log_level).unwrap_or_default()
+    }
+}
+
 struct Init {
     config: cfg::Config,
     driver: Driver,
 }
 
-fn init() -> io::Result<Init> {
-    let config = read_config();
+fn init(config: crate::Result<Config>) -> io::Result<Init> {
+    let config = config_or_default(config);
     print_config(&config);
     let driver = Driver::new()?;
 
     Ok(Init { config, driver })
 }
 
+/// loads the script given in the config, relative to the config directory
+fn load_script(config: &Config) {
+    let path = config.script.as_ref().map(|path| match cfg::config_dir() {
+        Ok(dir) => dir.join(path),
+        Err(_) => path.clone(),
+    });
+
+    script::load(
+        path.as_deref(),
+        Duration::from_millis(config.script_timeout_ms),
+    )
+}
+
 fn read_config() -> Config {
-    cfg::read_config()
-        .map_err(|e| eprintln!("config error: {e}"))
+    config_or_default(cfg::read_config())
+}
+
+fn config_or_default(config: crate::Result<Config>) -> Config {
+    config
+        .map_err(|e| report!(Error, Config, "config error: {e}"))
         .unwrap_or_default()
 }
 
 fn print_config(config: &Config) {
-    eprintln!("default sensitivity = {}", config.default_sensitivity);
+    report!(
+        Info,
+        Config,
+        "default sensitivity = {}",
+        config.default_sensitivity
+    );
 
     if !config.processes.is_empty() {
-        eprintln!("scaling for:");
+        report!(Info, Config, "scaling for:");
 
         for (process, entry) in &config.processes {
-            eprintln!("{} ({})", process.display(), entry.sensitivity)
+            report!(
+                Info,
+                Config,
+                "{} ({})",
+                process.display(),
+                entry.sensitivity
+            )
         }
     }
 }
 
-fn on_focus_changed(config: &Config, driver: &mut Driver, process: &hook::Process) {
-    let res = config
-        .processes
-        .get(process.exe())
-        .or_else(|| config.processes.get(Path::new(process.exe().file_name()?)))
-        .filter(|entry| entry.cursor_matches(process))
-        .map(|entry| driver.set_sens(entry.sensitivity))
-        .unwrap_or_else(|| driver.set_sens(config.default_sensitivity));
-
-    if let Err(e) = res {
-        eprint!("{e}")
+/// resolves the sensitivity like the daemon applies it, with the script
+/// deciding before the config entries unless there's an override
+fn resolve<'a>(config: &'a Config, context: &Context<'a>) -> rules::Resolution<'a> {
+    let mut resolution = rules::resolve(config, context);
+
+    if let (None, Some(focus)) = (resolution.forced, context.focus) {
+        resolution.scripted = script::resolve(|| script::Input {
+            exe: focus.exe().to_owned(),
+            title: focus.window.map(Window::title).unwrap_or_default(),
+            class: focus.window.map(Window::class).unwrap_or_default(),
+            cursor_hidden: focus.cursor_hidden(),
+        })
     }
+
+    resolution
 }
 
-impl cfg::Entry {
-    fn cursor_matches(&self, process: &hook::Process) -> bool {
-        !self.only_if_cursor_hidden || process.cursor_hidden.unwrap_or_else(cursor::hidden)
+fn on_focus_changed(config: &Config, driver: &mut Driver, context: &Context) -> hook::Applied {
+    let resolution = resolve(config, context);
+    let cursor_hidden = context
+        .focus
+        .map_or_else(cursor::hidden, Process::cursor_hidden);
+
+    report!(Debug, Rule, "cursor hidden: {cursor_hidden}");
+
+    if resolution.candidates.is_empty() {
+        report!(Debug, Rule, "no entries match")
     }
+
+    for candidate in &resolution.candidates {
+        report!(Debug, Rule, "{candidate}")
+    }
+
+    let sensitivity = resolution.sensitivity();
+    let focus = context.focus.map(|process| process.exe().to_owned());
+    let applied =
+        |source, process, on_enter: &Option<String>, on_exit: &Option<String>| commands::Applied {
+            source,
+            process,
+            sensitivity,
+            on_enter: on_enter.clone(),
+            on_exit: on_exit.clone(),
+        };
+
+    let unkeyed = |source| hook::Applied {
+        entry: None,
+        source,
+    };
+
+    let (applied, status) = match (resolution.forced, resolution.scripted, resolution.chosen()) {
+        (Some(_), ..) => {
+            report!(Debug, Rule, "sensitivity: {sensitivity} (overridden)");
+            (
+                applied(commands::Source::Override, focus, &None, &None),
+                unkeyed(msg::Source::Override),
+            )
+        }
+        (None, Some(_), _) => {
+            report!(Debug, Rule, "sensitivity: {sensitivity} (from the script)");
+            (
+                applied(commands::Source::Script, focus, &None, &None),
+                unkeyed(msg::Source::Script),
+            )
+        }
+        (None, None, Some(candidate)) => {
+            let key = candidate.rule.key;
+            let held = resolution
+                .held
+                .map(|held| format!(" while {} is held", held.input))
+                .unwrap_or_default();
+
+            report!(
+                Debug,
+                Rule,
+                "sensitivity: {sensitivity} (from the entry for {}{held})",
+                key.display()
+            );
+
+            let source = match resolution.held {
+                Some(_) => msg::Source::Held,
+                None => msg::Source::Entry,
+            };
+
+            (
+                applied(
+                    commands::Source::Entry(key.to_owned()),
+                    Some(candidate.exe.to_owned()),
+                    &candidate.rule.entry.on_enter,
+                    &candidate.rule.entry.on_exit,
+                ),
+                hook::Applied {
+                    entry: Some(key.to_owned()),
+                    source,
+                },
+            )
+        }
+        (None, None, None) => {
+            report!(
+                Debug,
+                Rule,
+                "sensitivity: {sensitivity} ({})",
+                resolution.fallback
+            );
+
+            let source = match resolution.fallback {
+                rules::Fallback::Default => msg::Source::Default,
+                rules::Fallback::Desktop => msg::Source::Desktop,
+                rules::Fallback::Unmatched => msg::Source::Unmatched,
+            };
+
+            (
+                applied(
+                    commands::Source::Default,
+                    focus,
+                    &config.default_on_enter,
+                    &config.default_on_exit,
+                ),
+                unkeyed(source),
+            )
+        }
+    };
+
+    // the handler is also called for changes that don't affect the
+    // resolution, like title changes
+    if commands::changes(&applied) {
+        msg::publish(msg::Event::RuleMatched {
+            rule: match &applied.source {
+                commands::Source::Entry(key) => Some(key.clone()),
+                _ => None,
+            },
+            sensitivity,
+        })
+    }
+
+    match driver.set_sens(sensitivity) {
+        Ok(true) => {
+            report!(Debug, Driver, "wrote sensitivity {sensitivity}");
+            msg::publish(msg::Event::SensitivityApplied { sensitivity })
+        }
+        Ok(false) => report!(
+            Debug,
+            Driver,
+            "skipped writing sensitivity {sensitivity}, it's already applied"
+        ),
+        Err(e) => report!(Error, Driver, "{e}"),
+    }
+
+    commands::switch(applied, Duration::from_secs(config.command_timeout_secs));
+
+    status
 }
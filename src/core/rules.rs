@@ -0,0 +1,613 @@
+use {
+    crate::{
+        cfg::{Activation, Config, Entry, WhileHeld},
+        core::{
+            cursor,
+            hook::{Context, Process},
+        },
+    },
+    std::{
+        fmt,
+        path::{Path, PathBuf},
+        time::Instant,
+    },
+};
+
+/// the window classes of the desktop and taskbar
+const SHELL_CLASSES: [&str; 4] = [
+    "Progman",
+    "WorkerW",
+    "Shell_TrayWnd",
+    "Shell_SecondaryTrayWnd",
+];
+
+/// whether a window with the given class of the process at `exe` belongs to
+/// the shell. all windows of explorer count, including its file browser.
+pub fn is_shell(exe: &Path, class: &str) -> bool {
+    exe.file_name()
+        .is_some_and(|name| name.eq_ignore_ascii_case("explorer.exe"))
+        || SHELL_CLASSES.contains(&class)
+}
+
+/// what's applied if no rule is selected
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fallback {
+    /// the default sensitivity, if scaling is suspended or no more specific
+    /// sensitivity is configured
+    Default,
+    /// nothing or the shell is focused
+    Desktop,
+    /// the focused process has no applicable rule
+    Unmatched,
+}
+
+impl fmt::Display for Fallback {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Fallback::Default => "default",
+            Fallback::Desktop => "desktop",
+            Fallback::Unmatched => "unmatched",
+        }
+        .fmt(f)
+    }
+}
+
+/// a config entry that matched a process
+#[derive(Debug, Clone, Copy)]
+pub struct Rule<'a> {
+    pub key: &'a Path,
+    pub entry: &'a Entry,
+}
+
+/// a sensitivity that takes precedence over all rules until it expires or is
+/// cleared
+#[derive(Debug, Clone, PartialEq)]
+pub struct Override {
+    pub sensitivity: f64,
+    /// limits the override to a process, given as a full path or file name
+    pub process: Option<PathBuf>,
+    pub expires: Option<Instant>,
+}
+
+impl Override {
+    pub fn applies_to(&self, focus: Option<&Process>) -> bool {
+        match (&self.process, focus.map(Process::exe)) {
+            (None, _) => true,
+            (Some(process), Some(exe)) => {
+                process == exe || exe.file_name().map(Path::new) == Some(process)
+            }
+            (Some(_), None) => false,
+        }
+    }
+
+    pub fn expired(&self, now: Instant) -> bool {
+        self.expires.is_some_and(|expires| expires <= now)
+    }
+}
+
+/// finds all entries for an executable, with entries for its full path coming
+/// before ones for its file name
+fn matching<'a>(config: &'a Config, exe: &'a Path) -> impl Iterator<Item = Rule<'a>> {
+    let name = exe.file_name().map(Path::new);
+
+    [Some(exe), name.filter(|&name| name != exe)]
+        .into_iter()
+        .flatten()
+        .filter_map(|key| config.processes.get_key_value(key))
+        .map(|(key, entry)| Rule { key, entry })
+}
+
+/// the most specific entry for an executable, if any
+pub fn entry_for<'a>(config: &'a Config, exe: &'a Path) -> Option<&'a Entry> {
+    matching(config, exe).next().map(|rule| rule.entry)
+}
+
+/// a rule that was considered for a process
+#[derive(Debug)]
+pub struct Candidate<'a> {
+    pub exe: &'a Path,
+    pub rule: Rule<'a>,
+    /// why the rule wasn't selected, or `None` if it was
+    pub rejection: Option<Rejection<'a>>,
+}
+
+impl fmt::Display for Candidate<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} for {} (sensitivity {}, priority {}): ",
+            self.rule.key.display(),
+            self.exe.display(),
+            self.rule.entry.sensitivity,
+            self.rule.entry.priority
+        )?;
+
+        match self.rejection {
+            Some(rejection) => write!(f, "rejected, {rejection}"),
+            None => write!(f, "selected"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rejection<'a> {
+    /// there's a more specific entry for the same executable
+    Shadowed { by: &'a Path },
+    /// the rule only applies while its process is focused
+    NotFocused,
+    /// the rule only applies while the cursor is hidden
+    CursorVisible,
+    /// another applicable rule has a higher priority, or the same priority and
+    /// is for the focused process
+    Outranked { by: &'a Path },
+}
+
+impl fmt::Display for Rejection<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Rejection::Shadowed { by } => write!(f, "shadowed by the entry for {}", by.display()),
+            Rejection::NotFocused => "only applies while focused".fmt(f),
+            Rejection::CursorVisible => "only applies while the cursor is hidden".fmt(f),
+            Rejection::Outranked { by } => write!(f, "outranked by the entry for {}", by.display()),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Resolution<'a> {
+    pub candidates: Vec<Candidate<'a>>,
+    /// the override that's applied instead of the selected rule, if any
+    pub forced: Option<&'a Override>,
+    /// the sensitivity the script returned instead of leaving it to the
+    /// rules, which is only asked if there's no override
+    pub scripted: Option<f64>,
+    /// the selected rule's `while_held`, if its input is held
+    pub held: Option<&'a WhileHeld>,
+    pub fallback: Fallback,
+    fallback_sensitivity: f64,
+}
+
+impl<'a> Resolution<'a> {
+    /// the rule that should currently be applied, if any
+    pub fn selected(&self) -> Option<Rule<'a>> {
+        self.chosen().map(|candidate| candidate.rule)
+    }
+
+    /// the candidate whose rule should currently be applied, if any
+    pub fn chosen(&self) -> Option<&Candidate<'a>> {
+        self.candidates
+            .iter()
+            .find(|candidate| candidate.rejection.is_none())
+    }
+
+    /// the sensitivity that should currently be applied
+    pub fn sensitivity(&self) -> f64 {
+        if let Some(forced) = self.forced {
+            return forced.sensitivity
+        }
+
+        if let Some(scripted) = self.scripted {
+            return scripted
+        }
+
+        if let Some(held) = self.held {
+            return held.sensitivity
+        }
+
+        self.selected()
+            .map(|rule| rule.entry.sensitivity)
+            .unwrap_or(self.fallback_sensitivity)
+    }
+}
+
+/// determines which rule should currently be applied by considering the rules
+/// for the focused process and all running processes.
+///
+/// rules for running processes only take precedence over the rule for the
+/// focused process if they have a higher priority. if none is selected, the
+/// `desktop` sensitivity applies while nothing or the shell is focused, and the
+/// `unmatched` one while any other process is, falling back to the default.
+pub fn resolve<'a>(config: &'a Config, context: &Context<'a>) -> Resolution<'a> {
+    let focus = context.focus.map(Process::exe);
+
+    let mut running = context
+        .running
+        .exes()
+        .filter(|&exe| Some(exe) != focus)
+        .collect::<Vec<_>>();
+
+    // sorted so that ties between running processes are resolved consistently
+    running.sort();
+    running.dedup();
+
+    let mut candidates = Vec::new();
+
+    for exe in focus.into_iter().chain(running) {
+        let mut rules = matching(config, exe);
+
+        if let Some(rule) = rules.next() {
+            let rejection = if rule.entry.activation == Activation::Focused && focus != Some(exe) {
+                Some(Rejection::NotFocused)
+            } else if !rule.entry.cursor_matches(context) {
+                Some(Rejection::CursorVisible)
+            } else {
+                None
+            };
+
+            candidates.push(Candidate {
+                exe,
+                rule,
+                rejection,
+            });
+
+            candidates.extend(rules.map(|shadowed| Candidate {
+                exe,
+                rule: shadowed,
+                rejection: Some(Rejection::Shadowed { by: rule.key }),
+            }))
+        }
+    }
+
+    let best = candidates
+        .iter()
+        .enumerate()
+        .filter(|(_, candidate)| candidate.rejection.is_none())
+        .reduce(|best, candidate| {
+            if candidate.1.rule.entry.priority > best.1.rule.entry.priority {
+                candidate
+            } else {
+                best
+            }
+        })
+        .map(|(i, candidate)| (i, candidate.rule.key));
+
+    if let Some((best, by)) = best {
+        for (i, candidate) in candidates.iter_mut().enumerate() {
+            if candidate.rejection.is_none() && i != best {
+                candidate.rejection = Some(Rejection::Outranked { by })
+            }
+        }
+    }
+
+    // only inputs held in the rule's own process count
+    let held = best.and_then(|(best, _)| {
+        let candidate = &candidates[best];
+        let process = context
+            .focus
+            .filter(|process| process.exe() == candidate.exe)?;
+
+        candidate
+            .rule
+            .entry
+            .while_held
+            .as_ref()
+            .filter(|while_held| process.held.holds(&while_held.input))
+    });
+
+    let (fallback, configured) = match (context.suspended, context.focus) {
+        (true, _) => (Fallback::Default, None),
+        (false, None) => (Fallback::Desktop, config.desktop),
+        (false, Some(process)) if process.shell => (Fallback::Desktop, config.desktop),
+        (false, Some(_)) => (Fallback::Unmatched, config.unmatched),
+    };
+
+    let (fallback, fallback_sensitivity) = match configured {
+        Some(sensitivity) => (fallback, sensitivity),
+        None => (Fallback::Default, config.default_sensitivity),
+    };
+
+    Resolution {
+        candidates,
+        held,
+        fallback,
+        fallback_sensitivity,
+        forced: context
+            .forced
+            .filter(|forced| forced.applies_to(context.focus)),
+        scripted: None,
+    }
+}
+
+impl Entry {
+    fn cursor_matches(&self, context: &Context) -> bool {
+        !self.only_if_cursor_hidden
+            || context.focus.map_or_else(
+                || context.cursor_hidden.unwrap_or_else(cursor::hidden),
+                Process::cursor_hidden,
+            )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        crate::{
+            cfg::Button,
+            core::hook::{
+                held::Input,
+                running::test::{started, FakeList},
+                ProcessEvent,
+                ProcessList,
+                Running,
+            },
+        },
+    };
+
+    fn entry(sensitivity: f64, activation: Activation, priority: i32) -> Entry {
+        Entry {
+            sensitivity,
+            activation,
+            priority,
+            ..<_>::default()
+        }
+    }
+
+    fn config() -> Config {
+        let mut config = Config::default();
+        config.processes.extend([
+            ("game.exe".into(), entry(2., Activation::Focused, 0)),
+            (
+                r"C:\games\game.exe".into(),
+                entry(3., Activation::Focused, 0),
+            ),
+            ("paint.exe".into(), entry(4., Activation::Running, 0)),
+            ("tablet.exe".into(), entry(5., Activation::Running, 1)),
+        ]);
+        config
+    }
+
+    fn resolved(config: &Config, focus: Option<&str>, running: &Running) -> Option<f64> {
+        let focus = focus.map(|exe| {
+            let mut process = Process::new(0, exe.into());
+            process.cursor_hidden = Some(false);
+            process
+        });
+        let context = Context {
+            focus: focus.as_ref(),
+            running,
+            forced: None,
+            suspended: false,
+            cursor_hidden: None,
+        };
+
+        resolve(config, &context)
+            .selected()
+            .map(|rule| rule.entry.sensitivity)
+    }
+
+    #[test]
+    fn prefers_full_path() {
+        let config = config();
+        let running = Running::default();
+
+        assert_eq!(
+            resolved(&config, Some(r"C:\games\game.exe"), &running),
+            Some(3.)
+        );
+        assert_eq!(resolved(&config, Some(r"D:\game.exe"), &running), Some(2.));
+        assert_eq!(resolved(&config, Some("other.exe"), &running), None)
+    }
+
+    #[test]
+    fn running_rules_follow_process_events() {
+        let config = config();
+        let mut running = Running::default();
+        let mut list = FakeList {
+            events: [
+                vec![started(1, r"C:\paint.exe")],
+                vec![started(2, "tablet.exe")],
+                vec![ProcessEvent::Exited { id: 2 }],
+                vec![ProcessEvent::Exited { id: 1 }],
+            ]
+            .into(),
+        };
+
+        let mut poll = |focus| {
+            running.apply(list.poll().unwrap());
+            resolved(&config, focus, &running)
+        };
+
+        // focused rules win ties
+        assert_eq!(poll(Some("game.exe")), Some(2.));
+        // higher priorities win regardless of focus
+        assert_eq!(poll(Some("game.exe")), Some(5.));
+        assert_eq!(poll(None), Some(4.));
+        assert_eq!(poll(None), None)
+    }
+
+    #[test]
+    fn focused_running_process_uses_running_rule() {
+        let config = config();
+        let mut running = Running::default();
+        running.apply([started(1, "paint.exe")]);
+
+        assert_eq!(resolved(&config, Some("paint.exe"), &running), Some(4.))
+    }
+
+    #[test]
+    fn rejections() {
+        let mut config = config();
+        config.processes.insert("hidden.exe".into(), Entry {
+            only_if_cursor_hidden: true,
+            ..<_>::default()
+        });
+
+        let mut focus = Process::new(0, "hidden.exe".into());
+        focus.cursor_hidden = Some(false);
+        let mut running = Running::default();
+        running.apply([started(1, "game.exe")]);
+
+        let context = Context {
+            focus: Some(&focus),
+            running: &running,
+            forced: None,
+            suspended: false,
+            cursor_hidden: None,
+        };
+        let rejections = resolve(&config, &context)
+            .candidates
+            .into_iter()
+            .map(|candidate| candidate.rejection)
+            .collect::<Vec<_>>();
+
+        assert_eq!(rejections, [
+            Some(Rejection::CursorVisible),
+            Some(Rejection::NotFocused)
+        ])
+    }
+
+    #[test]
+    fn held_inputs() {
+        let mut config = config();
+        let scoped = |sensitivity| {
+            Some(WhileHeld {
+                input: crate::cfg::Input::Button(Button::Right),
+                sensitivity,
+            })
+        };
+        config
+            .processes
+            .get_mut(Path::new("game.exe"))
+            .unwrap()
+            .while_held = scoped(0.5);
+        config
+            .processes
+            .get_mut(Path::new("tablet.exe"))
+            .unwrap()
+            .while_held = scoped(0.25);
+
+        let right = Input::Button(Button::Right);
+        let sensitivity = |exe: &str, running: &Running, held: bool| {
+            let mut process = Process::new(0, exe.into());
+
+            if held {
+                process.held.press(right);
+            }
+
+            let context = Context {
+                focus: Some(&process),
+                running,
+                forced: None,
+                suspended: false,
+                cursor_hidden: None,
+            };
+
+            resolve(&config, &context).sensitivity()
+        };
+
+        let mut running = Running::default();
+        assert_eq!(sensitivity("game.exe", &running, false), 2.);
+        assert_eq!(sensitivity("game.exe", &running, true), 0.5);
+
+        // the selected rule is for a process that isn't focused
+        running.apply([started(1, "tablet.exe")]);
+        assert_eq!(sensitivity("game.exe", &running, true), 5.)
+    }
+
+    #[test]
+    fn fallbacks() {
+        let mut config = config();
+        let running = Running::default();
+        let resolved = |config: &Config, exe: Option<&str>, shell: bool, suspended: bool| {
+            let focus = exe.map(|exe| {
+                let mut process = Process::new(0, exe.into());
+                process.shell = shell;
+                process
+            });
+            let context = Context {
+                focus: focus.as_ref(),
+                running: &running,
+                forced: None,
+                suspended,
+                cursor_hidden: None,
+            };
+            let resolution = resolve(config, &context);
+
+            (resolution.fallback, resolution.sensitivity())
+        };
+
+        // without them, the default is used
+        assert_eq!(
+            resolved(&config, Some("other.exe"), false, false),
+            (Fallback::Default, 1.)
+        );
+        assert_eq!(
+            resolved(&config, None, false, false),
+            (Fallback::Default, 1.)
+        );
+
+        config.desktop = Some(0.8);
+        config.unmatched = Some(1.2);
+
+        assert_eq!(
+            resolved(&config, Some("other.exe"), false, false),
+            (Fallback::Unmatched, 1.2)
+        );
+        assert_eq!(
+            resolved(&config, None, false, false),
+            (Fallback::Desktop, 0.8)
+        );
+        assert_eq!(
+            resolved(&config, Some(r"C:\Windows\explorer.exe"), true, false),
+            (Fallback::Desktop, 0.8)
+        );
+        assert_eq!(
+            resolved(&config, None, false, true),
+            (Fallback::Default, 1.)
+        );
+        // entries still take precedence
+        assert_eq!(resolved(&config, Some("game.exe"), false, false).1, 2.);
+
+        assert!(is_shell(Path::new(r"C:\Windows\Explorer.EXE"), ""));
+        assert!(is_shell(Path::new(r"C:\Tools\shell.exe"), "Shell_TrayWnd"));
+        assert!(!is_shell(Path::new(r"C:\game.exe"), "UnityWndClass"))
+    }
+
+    #[test]
+    fn overrides() {
+        let config = config();
+        let running = Running::default();
+        let game = Process::new(0, r"C:\games\game.exe".into());
+        let other = Process::new(1, "other.exe".into());
+
+        let sensitivity = |focus, forced| {
+            let context = Context {
+                focus,
+                running: &running,
+                forced,
+                suspended: false,
+                cursor_hidden: None,
+            };
+
+            resolve(&config, &context).sensitivity()
+        };
+
+        let everywhere = Override {
+            sensitivity: 6.,
+            process: None,
+            expires: None,
+        };
+
+        assert_eq!(sensitivity(Some(&game), Some(&everywhere)), 6.);
+        assert_eq!(sensitivity(None, Some(&everywhere)), 6.);
+
+        let for_game = Override {
+            process: Some("game.exe".into()),
+            ..everywhere.clone()
+        };
+
+        assert_eq!(sensitivity(Some(&game), Some(&for_game)), 6.);
+        assert_eq!(sensitivity(Some(&other), Some(&for_game)), 1.);
+        assert_eq!(sensitivity(None, Some(&for_game)), 1.);
+
+        let now = Instant::now();
+        let expiring = Override {
+            expires: Some(now),
+            ..everywhere
+        };
+
+        assert!(expiring.expired(now));
+        assert!(!expiring.expired(now - std::time::Duration::from_secs(1)))
+    }
+}
@@ -0,0 +1,221 @@
+//! a script that can decide the sensitivity instead of the config entries. it
+//! has to define a `resolve(process)` function, which gets a map with the
+//! `exe`, `name`, `title`, `class` and `cursor_hidden` of the focused process
+//! and returns a sensitivity, a map with a `sensitivity` or `()` to use the
+//! config entries.
+
+use {
+    crate::{msg, output::report},
+    rhai::{
+        module_resolvers::DummyModuleResolver,
+        Dynamic,
+        Engine,
+        EvalAltResult,
+        Map,
+        Scope,
+        AST,
+    },
+    std::{
+        fs,
+        path::{Path, PathBuf},
+        sync::{Arc, Mutex},
+        time::{Duration, Instant},
+    },
+};
+
+/// what the script is told about the focused process
+pub struct Input {
+    pub exe: PathBuf,
+    pub title: String,
+    pub class: String,
+    pub cursor_hidden: bool,
+}
+
+impl Input {
+    fn to_map(&self) -> Map {
+        let name = self
+            .exe
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        Map::from_iter([
+            ("exe".into(), self.exe.to_string_lossy().into_owned().into()),
+            ("name".into(), name.into()),
+            ("title".into(), self.title.clone().into()),
+            ("class".into(), self.class.clone().into()),
+            ("cursor_hidden".into(), self.cursor_hidden.into()),
+        ])
+    }
+}
+
+pub struct Script {
+    engine: Engine,
+    ast: AST,
+    timeout: Duration,
+    /// when the current call has to be finished
+    deadline: Arc<Mutex<Instant>>,
+}
+
+impl Script {
+    pub fn compile(source: &str, timeout: Duration) -> Result<Script, String> {
+        let deadline = Arc::new(Mutex::new(Instant::now()));
+        let progress = deadline.clone();
+        let mut engine = Engine::new();
+
+        // scripts can't load other code and only get to see their input
+        engine
+            .set_module_resolver(DummyModuleResolver::new())
+            .disable_symbol("eval")
+            .set_max_call_levels(32)
+            .set_max_string_size(64 * 1024)
+            .set_max_array_size(10_000)
+            .set_max_map_size(10_000)
+            .on_print(|text| report!(Info, Rule, "script: {text}"))
+            .on_debug(|text, _, _| report!(Debug, Rule, "script: {text}"))
+            .on_progress(move |_| {
+                (Instant::now() > *progress.lock().unwrap()).then_some(Dynamic::UNIT)
+            });
+
+        let ast = engine.compile(source).map_err(|e| e.to_string())?;
+
+        Ok(Script {
+            engine,
+            ast,
+            timeout,
+            deadline,
+        })
+    }
+
+    /// returns `None` if the config entries should be used
+    pub fn resolve(&self, input: &Input) -> Result<Option<f64>, String> {
+        *self.deadline.lock().unwrap() = Instant::now() + self.timeout;
+
+        let result = self
+            .engine
+            .call_fn::<Dynamic>(&mut Scope::new(), &self.ast, "resolve", (input.to_map(),))
+            .map_err(|e| match *e {
+                EvalAltResult::ErrorTerminated(..) => {
+                    format!("took longer than {}ms", self.timeout.as_millis())
+                }
+                e => e.to_string(),
+            })?;
+
+        if result.is_unit() {
+            return Ok(None)
+        }
+
+        let sensitivity = match result.try_cast_result::<Map>() {
+            Ok(mut map) => map
+                .remove("sensitivity")
+                .ok_or("the returned map has no sensitivity")?,
+            Err(result) => result,
+        };
+
+        let sensitivity = sensitivity
+            .as_float()
+            .or_else(|_| sensitivity.as_int().map(|int| int as f64))
+            .map_err(|ty| format!("expected a sensitivity, but got {ty}"))?;
+
+        // the driver would be set to whatever is returned
+        msg::valid_sensitivity(sensitivity).map(Some)
+    }
+}
+
+static SCRIPT: Mutex<Option<Script>> = Mutex::new(None);
+
+/// loads the script at `path` if given, replacing the current one
+pub fn load(path: Option<&Path>, timeout: Duration) {
+    let script = path.and_then(|path| {
+        fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|source| Script::compile(&source, timeout))
+            .map_err(|e| report!(Error, Config, "couldn't load {}: {e}", path.display()))
+            .ok()
+    });
+
+    *SCRIPT.lock().unwrap() = script;
+}
+
+pub fn loaded() -> bool {
+    SCRIPT.lock().unwrap().is_some()
+}
+
+/// calls the script if there is one, returning `None` if it wants the config
+/// entries to be used or fails
+pub fn resolve(input: impl FnOnce() -> Input) -> Option<f64> {
+    let script = SCRIPT.lock().unwrap();
+
+    match script.as_ref()?.resolve(&input()) {
+        Ok(sensitivity) => sensitivity,
+        Err(e) => {
+            report!(Warn, Rule, "script failed, using the config entries: {e}");
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const TIMEOUT: Duration = Duration::from_millis(100);
+
+    fn input() -> Input {
+        Input {
+            exe: r"C:\game.exe".into(),
+            title: "World Map".into(),
+            class: "UnityWndClass".into(),
+            cursor_hidden: true,
+        }
+    }
+
+    fn resolve(source: &str) -> Result<Option<f64>, String> {
+        Script::compile(source, TIMEOUT)?.resolve(&input())
+    }
+
+    #[test]
+    fn results() {
+        let script = r#"
+            fn resolve(process) {
+                if process.name == "game.exe" && process.title.contains("Map") {
+                    return 0.8;
+                }
+            }
+        "#;
+
+        assert_eq!(resolve(script), Ok(Some(0.8)));
+        assert_eq!(
+            Script::compile(script, TIMEOUT).unwrap().resolve(&Input {
+                title: "Game".into(),
+                ..input()
+            }),
+            Ok(None)
+        );
+        assert_eq!(resolve("fn resolve(p) { 2 }"), Ok(Some(2.)));
+        assert_eq!(
+            resolve("fn resolve(p) { #{ sensitivity: 1.5 } }"),
+            Ok(Some(1.5))
+        );
+        assert_eq!(
+            resolve(r#"fn resolve(p) { if p.cursor_hidden { "fast" } }"#),
+            Err("expected a sensitivity, but got string".into())
+        );
+        assert_eq!(
+            resolve("fn resolve(p) { 0 }"),
+            Err("invalid sensitivity 0, it has to be a positive number".into())
+        );
+        assert!(resolve("fn resolve(p) { 0.0 / 0.0 }").is_err())
+    }
+
+    #[test]
+    fn sandbox() {
+        assert_eq!(
+            resolve("fn resolve(p) { loop {} }"),
+            Err("took longer than 100ms".into())
+        );
+        assert!(resolve(r#"fn resolve(p) { eval("1") }"#).is_err());
+        assert!(resolve(r#"import "other" as other; fn resolve(p) { 1 }"#).is_err());
+        assert!(resolve("fn other(p) { 1 }").is_err())
+    }
+}
@@ -0,0 +1,297 @@
+//! the record a running instance leaves behind so other invocations can find
+//! and talk to it. it's only valid while the instance holds the lock file,
+//! which the system releases even if the instance doesn't exit properly.
+
+use {
+    crate::{
+        msg::{self, codec::PROTOCOL_VERSION},
+        windows::{process, thread},
+    },
+    serde::{Deserialize, Serialize},
+    std::{
+        env,
+        fs::{self, File, OpenOptions},
+        io,
+        os::windows::fs::OpenOptionsExt,
+        path::{Path, PathBuf},
+        time::{Duration, SystemTime},
+    },
+    winapi::shared::winerror::ERROR_SHARING_VIOLATION,
+};
+
+const MAGIC: &[u8; 4] = b"SNSC";
+const FORMAT_VERSION: u16 = 2;
+const HEADER_LEN: usize = MAGIC.len() + 2;
+
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// how often and how long apart acquiring the lock is attempted while it's
+/// held by something else
+const ACQUIRE_ATTEMPTS: u32 = 5;
+const ACQUIRE_INTERVAL: Duration = Duration::from_millis(20);
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Record {
+    pub pid: u32,
+    /// the thread receiving requests
+    pub thread_id: u32,
+    /// seconds since the unix epoch
+    pub started_at: u64,
+    pub exe: PathBuf,
+    pub version: String,
+    pub protocol_version: u16,
+    /// the security identifier of the user, see
+    /// [`process::current_user_sid`]
+    pub user: String,
+    pub session: u32,
+}
+
+impl Record {
+    /// the record for the current process, started at `started`
+    pub fn current(started: SystemTime) -> io::Result<Record> {
+        let pid = std::process::id();
+
+        Ok(Record {
+            pid,
+            thread_id: thread::current_id().into(),
+            started_at: msg::unix_secs(started),
+            exe: env::current_exe()?,
+            version: VERSION.into(),
+            protocol_version: PROTOCOL_VERSION,
+            user: process::current_user_sid()?,
+            session: process::session_id(pid)?,
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        serde_json::to_writer(&mut bytes, self).expect("records are always serializable");
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<Record> {
+        let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+
+        if bytes.len() < HEADER_LEN || !bytes.starts_with(MAGIC) {
+            return Err(invalid("not an instance record".into()))
+        }
+
+        let version = u16::from_le_bytes([bytes[4], bytes[5]]);
+
+        if version != FORMAT_VERSION {
+            return Err(invalid(format!(
+                "unsupported instance record format {version}"
+            )))
+        }
+
+        serde_json::from_slice(&bytes[HEADER_LEN..])
+            .map_err(|e| invalid(format!("invalid instance record: {e}")))
+    }
+
+    pub fn read(path: impl AsRef<Path>) -> io::Result<Record> {
+        Record::from_bytes(&fs::read(path)?)
+    }
+
+    pub fn write(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, self.to_bytes())
+    }
+
+    /// makes sure the instance can be talked to by `current`
+    pub fn check_compatible(&self, current: &Record) -> io::Result<()> {
+        if self.session != current.session || self.user != current.user {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!(
+                    "the running instance belongs to {} in session {}",
+                    self.user, self.session
+                ),
+            ))
+        }
+
+        if self.version != current.version || self.protocol_version != current.protocol_version {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!(
+                    "the running instance is version {} (protocol {}), but this is version {} \
+                     (protocol {}). stop it with the same version or end process {}",
+                    self.version,
+                    self.protocol_version,
+                    current.version,
+                    current.protocol_version,
+                    self.pid
+                ),
+            ))
+        }
+
+        Ok(())
+    }
+}
+
+/// held by the running instance for as long as it runs
+pub struct Lock {
+    file: Option<File>,
+    path: PathBuf,
+}
+
+impl Lock {
+    /// acquires the lock, returning `None` if another process holds it
+    pub fn acquire(path: impl Into<PathBuf>) -> io::Result<Option<Lock>> {
+        let path = path.into();
+
+        // `is_locked` holds the file for a moment while checking it, which
+        // mustn't be mistaken for another instance
+        for attempt in 1..=ACQUIRE_ATTEMPTS {
+            match open_exclusive(&path, true) {
+                Ok(file) => {
+                    return Ok(Some(Lock {
+                        file: Some(file),
+                        path,
+                    }))
+                }
+                Err(e) if is_sharing_violation(&e) && attempt < ACQUIRE_ATTEMPTS => {
+                    std::thread::sleep(ACQUIRE_INTERVAL)
+                }
+                Err(e) if is_sharing_violation(&e) => return Ok(None),
+                Err(e) => return Err(e),
+            }
+        }
+
+        unreachable!("the last attempt always returns")
+    }
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        // the file can only be removed once it's closed
+        self.file.take();
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// whether another process holds the lock. sharing violations are symmetric,
+/// so checking requires holding the file exclusively for a moment as well
+pub fn is_locked(path: impl AsRef<Path>) -> io::Result<bool> {
+    match open_exclusive(path.as_ref(), false) {
+        Ok(_) => Ok(false),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+        Err(e) if is_sharing_violation(&e) => Ok(true),
+        Err(e) => Err(e),
+    }
+}
+
+fn open_exclusive(path: &Path, create: bool) -> io::Result<File> {
+    OpenOptions::new()
+        .write(true)
+        .create(create)
+        .truncate(false)
+        .share_mode(0)
+        .open(path)
+}
+
+fn is_sharing_violation(e: &io::Error) -> bool {
+    e.raw_os_error() == Some(ERROR_SHARING_VIOLATION as _)
+}
+
+pub fn record_file() -> PathBuf {
+    env::temp_dir().join("senscale-instance")
+}
+
+pub fn lock_file() -> PathBuf {
+    env::temp_dir().join("senscale-instance.lock")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn record() -> Record {
+        Record {
+            pid: 1234,
+            thread_id: 5678,
+            started_at: 1_600_000_000,
+            exe: r"C:\senscale.exe".into(),
+            version: VERSION.into(),
+            protocol_version: PROTOCOL_VERSION,
+            user: "S-1-5-21-1000".into(),
+            session: 1,
+        }
+    }
+
+    #[test]
+    fn record_roundtrip() {
+        let bytes = record().to_bytes();
+
+        assert_eq!(&bytes[..6], b"SNSC\x02\x00");
+        assert_eq!(Record::from_bytes(&bytes).unwrap(), record())
+    }
+
+    #[test]
+    fn invalid_records() {
+        let error = |bytes: &[u8]| Record::from_bytes(bytes).unwrap_err().to_string();
+
+        // the old format
+        assert_eq!(error(&[255; 12]), "not an instance record");
+        assert_eq!(error(b"SNSC"), "not an instance record");
+        assert_eq!(
+            error(b"SNSC\x01\x00{}"),
+            "unsupported instance record format 1"
+        );
+        assert!(error(b"SNSC\x02\x00{}").starts_with("invalid instance record"))
+    }
+
+    #[test]
+    fn compatibility() {
+        assert!(record().check_compatible(&record()).is_ok());
+
+        let other_session = Record {
+            session: 2,
+            ..record()
+        };
+        assert_eq!(
+            other_session
+                .check_compatible(&record())
+                .unwrap_err()
+                .kind(),
+            io::ErrorKind::PermissionDenied
+        );
+
+        let old = Record {
+            version: "0.1.0".into(),
+            protocol_version: 0,
+            ..record()
+        };
+        assert_eq!(
+            old.check_compatible(&record()).unwrap_err().to_string(),
+            format!(
+                "the running instance is version 0.1.0 (protocol 0), but this is version \
+                 {VERSION} (protocol {PROTOCOL_VERSION}). stop it with the same version or end \
+                 process 1234"
+            )
+        )
+    }
+
+    #[test]
+    fn lock() {
+        let path = env::temp_dir().join(format!("senscale-test-{}.lock", std::process::id()));
+
+        assert!(!is_locked(&path).unwrap());
+
+        let lock = Lock::acquire(&path).unwrap().unwrap();
+        assert!(is_locked(&path).unwrap());
+        assert!(Lock::acquire(&path).unwrap().is_none());
+
+        drop(lock);
+        assert!(!is_locked(&path).unwrap());
+        assert!(Lock::acquire(&path).unwrap().is_some());
+
+        // a concurrent check doesn't prevent acquiring the lock
+        let probe = open_exclusive(&path, true).unwrap();
+        let released = std::thread::spawn(move || {
+            std::thread::sleep(ACQUIRE_INTERVAL);
+            drop(probe)
+        });
+        assert!(Lock::acquire(&path).unwrap().is_some());
+        released.join().unwrap()
+    }
+}
@@ -0,0 +1,526 @@
+//! structured log records written by the running instance, one JSON object
+//! per line
+
+use {
+    crate::{cli, windows::file::file_id, Result},
+    serde::{Deserialize, Serialize},
+    std::{
+        env,
+        ffi::OsString,
+        fmt,
+        fs::{self, File, OpenOptions},
+        io::{self, BufRead, BufReader, Write},
+        path::{Path, PathBuf},
+        str::FromStr,
+        thread,
+        time::{Duration, SystemTime, UNIX_EPOCH},
+    },
+};
+
+const FOLLOW_INTERVAL: Duration = Duration::from_millis(250);
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Level {
+    Debug,
+    #[default]
+    Info,
+    Warn,
+    Error,
+}
+
+impl Level {
+    fn name(self) -> &'static str {
+        match self {
+            Level::Debug => "debug",
+            Level::Info => "info",
+            Level::Warn => "warn",
+            Level::Error => "error",
+        }
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.name().fmt(f)
+    }
+}
+
+impl FromStr for Level {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        [Level::Debug, Level::Info, Level::Warn, Level::Error]
+            .into_iter()
+            .find(|level| level.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| format!("unknown level {s}, expected debug, info, warn or error"))
+    }
+}
+
+/// what a record is about
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Kind {
+    General,
+    /// the focused process changed
+    Focus,
+    /// a config entry was matched
+    Rule,
+    /// settings were written to the driver
+    Driver,
+    Config,
+    /// processes were listed or watched
+    Process,
+    /// the instance was controlled by a client, e.g. paused or overridden
+    Control,
+    /// a command configured for an entry was run
+    Command,
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Kind::General => "general",
+            Kind::Focus => "focus",
+            Kind::Rule => "rule",
+            Kind::Driver => "driver",
+            Kind::Config => "config",
+            Kind::Process => "process",
+            Kind::Control => "control",
+            Kind::Command => "command",
+        };
+
+        name.fmt(f)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Record {
+    /// milliseconds since the unix epoch
+    pub time: u64,
+    pub level: Level,
+    pub kind: Kind,
+    pub message: String,
+}
+
+impl Record {
+    pub fn now(level: Level, kind: Kind, message: String) -> Record {
+        Record {
+            time: unix_millis(SystemTime::now()),
+            level,
+            kind,
+            message,
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("records are always serializable")
+    }
+
+    pub fn parse(line: &str) -> serde_json::Result<Record> {
+        serde_json::from_str(line)
+    }
+}
+
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} {:<5} {}: {}",
+            Timestamp(self.time),
+            self.level,
+            self.kind,
+            self.message
+        )
+    }
+}
+
+fn unix_millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// formats milliseconds since the unix epoch as an RFC 3339 timestamp in UTC
+struct Timestamp(u64);
+
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (secs, millis) = (self.0 / 1000, self.0 % 1000);
+        let (days, secs) = ((secs / 86400) as i64, secs % 86400);
+
+        // converts days since the epoch to a civil date, see
+        // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+        let z = days + 719468;
+        let era = z.div_euclid(146097);
+        let doe = z.rem_euclid(146097);
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + i64::from(month <= 2);
+
+        write!(
+            f,
+            "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}.{millis:03}Z",
+            secs / 3600,
+            secs / 60 % 60,
+            secs % 60
+        )
+    }
+}
+
+pub fn log_file() -> PathBuf {
+    env::temp_dir().join("senscale-log")
+}
+
+fn open_append(path: &Path) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+/// when a file is rotated
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Rotation {
+    /// in bytes
+    pub max_size: u64,
+    pub max_age: Duration,
+    pub keep: usize,
+}
+
+/// a file that's moved to `<path>.1` once it gets too large or old, with
+/// previously rotated files moving to `<path>.2` and so on
+pub struct RotatingFile {
+    path: PathBuf,
+    rotation: Rotation,
+    file: File,
+    len: u64,
+    created: SystemTime,
+}
+
+impl RotatingFile {
+    pub fn open(path: impl Into<PathBuf>, rotation: Rotation) -> io::Result<RotatingFile> {
+        let path = path.into();
+        let file = open_append(&path)?;
+        let metadata = file.metadata()?;
+
+        Ok(RotatingFile {
+            path,
+            rotation,
+            file,
+            len: metadata.len(),
+            created: metadata.created().unwrap_or_else(|_| SystemTime::now()),
+        })
+    }
+
+    /// whether the file should be rotated before writing `len` more bytes
+    fn should_rotate(&self, len: u64, now: SystemTime) -> bool {
+        let too_old = now
+            .duration_since(self.created)
+            .is_ok_and(|age| age >= self.rotation.max_age);
+
+        // files are never left empty
+        self.len > 0 && (self.len + len > self.rotation.max_size || too_old)
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        let keep = self.rotation.keep;
+
+        if keep == 0 {
+            fs::remove_file(&self.path)?
+        } else {
+            ignore_missing(fs::remove_file(rotated_path(&self.path, keep)))?;
+
+            for n in (1..keep).rev() {
+                ignore_missing(fs::rename(
+                    rotated_path(&self.path, n),
+                    rotated_path(&self.path, n + 1),
+                ))?
+            }
+
+            // other handles to the file, like our stderr, keep writing to the
+            // rotated file
+            fs::rename(&self.path, rotated_path(&self.path, 1))?
+        }
+
+        self.file = open_append(&self.path)?;
+        self.len = 0;
+        self.created = SystemTime::now();
+
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.should_rotate(buf.len() as u64, SystemTime::now()) {
+            self.rotate()?
+        }
+
+        let written = self.file.write(buf)?;
+        self.len += written as u64;
+
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+fn ignore_missing(res: io::Result<()>) -> io::Result<()> {
+    match res {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        res => res,
+    }
+}
+
+/// the path `path` is moved to when it's rotated for the `n`th time
+fn rotated_path(path: &Path, n: usize) -> PathBuf {
+    let mut path = OsString::from(path);
+    path.push(format!(".{n}"));
+    path.into()
+}
+
+/// the files `path` was rotated to, from newest to oldest
+pub fn rotated_files(path: &Path) -> impl Iterator<Item = PathBuf> + '_ {
+    (1..)
+        .map(|n| rotated_path(path, n))
+        .take_while(|path| path.exists())
+}
+
+struct Filter {
+    since: Option<u64>,
+    level: Level,
+}
+
+impl Filter {
+    fn matches(&self, record: &Record) -> bool {
+        record.level >= self.level && self.since.is_none_or(|since| record.time >= since)
+    }
+}
+
+/// prints the complete records that can be read, keeping an incomplete last
+/// line in `partial`
+fn print_records(
+    reader: &mut impl BufRead,
+    partial: &mut String,
+    filter: &Filter,
+    json: bool,
+) -> io::Result<()> {
+    loop {
+        if reader.read_line(partial)? == 0 || !partial.ends_with('\n') {
+            return Ok(())
+        }
+
+        let line = std::mem::take(partial);
+
+        // lines that can't be parsed were probably written by a different
+        // version and are skipped
+        if let Ok(record) = Record::parse(line.trim_end()) {
+            if filter.matches(&record) {
+                if json {
+                    print!("{line}")
+                } else {
+                    println!("{record}")
+                }
+            }
+        }
+    }
+}
+
+pub fn logs(args: cli::Logs) -> Result {
+    let filter = Filter {
+        // reaching back further than the system's clock can represent means
+        // there's no lower bound
+        since: args
+            .since
+            .and_then(|since| SystemTime::now().checked_sub(since))
+            .map(unix_millis),
+        level: args.level,
+    };
+
+    let path = log_file();
+    let file = File::open(&path).map_err(|e| {
+        if e.kind() == io::ErrorKind::NotFound {
+            io::Error::new(e.kind(), "nothing was logged yet")
+        } else {
+            e
+        }
+    })?;
+
+    let mut partial = String::new();
+
+    for rotated in rotated_files(&path).collect::<Vec<_>>().into_iter().rev() {
+        print_records(
+            &mut BufReader::new(File::open(rotated)?),
+            &mut partial,
+            &filter,
+            args.json,
+        )?;
+        partial.clear()
+    }
+
+    let mut reader = BufReader::new(file);
+    print_records(&mut reader, &mut partial, &filter, args.json)?;
+
+    if !args.follow {
+        return Ok(())
+    }
+
+    loop {
+        thread::sleep(FOLLOW_INTERVAL);
+
+        // the path may be missing for a moment while the log is rotated
+        let current = match File::open(&path) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            res => Some(res?),
+        };
+
+        print_records(&mut reader, &mut partial, &filter, args.json)?;
+
+        // the log was rotated or replaced, so the rest of the old file was just
+        // printed and the new one is read from the beginning
+        if let Some(current) = current {
+            if file_id(&current)? != file_id(reader.get_ref())? {
+                reader = BufReader::new(current);
+                partial.clear();
+                print_records(&mut reader, &mut partial, &filter, args.json)?
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn record() -> Record {
+        Record {
+            time: 1_760_875_200_123,
+            level: Level::Warn,
+            kind: Kind::Driver,
+            message: "couldn't write \"settings\"\nto the driver".into(),
+        }
+    }
+
+    #[test]
+    fn record_roundtrip() {
+        let json = record().to_json();
+
+        assert_eq!(
+            json,
+            concat!(
+                r#"{"time":1760875200123,"level":"warn","kind":"driver","#,
+                r#""message":"couldn't write \"settings\"\nto the driver"}"#
+            )
+        );
+        assert!(!json.contains('\n'));
+        assert_eq!(Record::parse(&json).unwrap(), record())
+    }
+
+    #[test]
+    fn record_text() {
+        let record = Record {
+            message: "oops".into(),
+            ..record()
+        };
+
+        assert_eq!(
+            record.to_string(),
+            "2025-10-19T12:00:00.123Z warn  driver: oops"
+        );
+        assert_eq!(Timestamp(0).to_string(), "1970-01-01T00:00:00.000Z");
+        assert_eq!(
+            Timestamp(951_782_400_000).to_string(),
+            "2000-02-29T00:00:00.000Z"
+        )
+    }
+
+    #[test]
+    fn levels() {
+        assert_eq!("WARN".parse(), Ok(Level::Warn));
+        assert!("verbose".parse::<Level>().is_err());
+        assert!(Level::Debug < Level::Info && Level::Warn < Level::Error)
+    }
+
+    #[test]
+    fn filters() {
+        let filter = Filter {
+            since: Some(1_760_875_200_000),
+            level: Level::Warn,
+        };
+
+        assert!(filter.matches(&record()));
+        assert!(!filter.matches(&Record {
+            level: Level::Info,
+            ..record()
+        }));
+        assert!(!filter.matches(&Record {
+            time: 1_760_875_199_999,
+            ..record()
+        }))
+    }
+
+    #[test]
+    fn partial_lines() {
+        let mut partial = String::new();
+        let filter = Filter {
+            since: None,
+            level: Level::Debug,
+        };
+        let json = record().to_json();
+        let (start, end) = json.split_at(10);
+
+        print_records(&mut start.as_bytes(), &mut partial, &filter, true).unwrap();
+        assert_eq!(partial, start);
+
+        print_records(
+            &mut format!("{end}\n").as_bytes(),
+            &mut partial,
+            &filter,
+            true,
+        )
+        .unwrap();
+        assert!(partial.is_empty())
+    }
+
+    #[test]
+    fn rotation() {
+        let dir = env::temp_dir().join(format!("senscale-test-{}-rotation", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir(&dir).unwrap();
+
+        let path = dir.join("log");
+        let rotation = Rotation {
+            max_size: 10,
+            max_age: Duration::from_secs(60),
+            keep: 2,
+        };
+        let mut file = RotatingFile::open(&path, rotation).unwrap();
+
+        for line in ["first\n", "second\n", "third\n", "fourth\n"] {
+            file.write_all(line.as_bytes()).unwrap()
+        }
+
+        let read = |path: &Path| fs::read_to_string(path).unwrap();
+        assert_eq!(read(&path), "fourth\n");
+        assert_eq!(
+            rotated_files(&path)
+                .map(|path| read(&path))
+                .collect::<Vec<_>>(),
+            ["third\n", "second\n"]
+        );
+
+        // continues where the previous file left off
+        let mut file = RotatingFile::open(&path, rotation).unwrap();
+        file.write_all(b"5\n").unwrap();
+        assert_eq!(read(&path), "fourth\n5\n");
+
+        let now = SystemTime::now();
+        assert!(!file.should_rotate(0, now));
+        assert!(file.should_rotate(0, now + Duration::from_secs(60)));
+
+        fs::remove_dir_all(dir).unwrap()
+    }
+}
@@ -3,7 +3,10 @@
 mod cfg;
 mod cli;
 mod core;
+mod instance;
+mod log;
 mod msg;
+mod output;
 mod thread_id;
 mod windows;
 mod wrap;
@@ -18,21 +21,30 @@ fn main() {
 
 fn run() -> Result {
     let args = argh::from_env::<cli::Args>();
+    let timeout = args.timeout;
 
     match args.command {
         cli::Command::Run(args) => {
             if args.foreground {
-                core::run(args.parent_thread)
+                core::run(args.output, args.verbose)
             } else {
-                wrap::run()
+                wrap::run(timeout, args.verbose)
             }?
         }
-        cli::Command::Stop(_) => wrap::stop()?,
-        cli::Command::Reload(_) => wrap::reload()?,
+        cli::Command::Stop(_) => wrap::stop(timeout)?,
+        cli::Command::Reload(_) => wrap::reload(timeout)?,
         cli::Command::Clean(_) => wrap::clean(),
-        cli::Command::PrintOutput(_) => wrap::print_output()?,
-        cli::Command::Adjust(args) => wrap::adjust(args.process)?,
+        cli::Command::PrintOutput(_) => wrap::print_output(timeout)?,
+        cli::Command::Adjust(args) => wrap::adjust(args.process, timeout)?,
         cli::Command::Config(_) => wrap::config()?,
+        cli::Command::Explain(args) => core::explain(args)?,
+        cli::Command::Status(args) => wrap::status(args.json, timeout)?,
+        cli::Command::Set(args) => wrap::set(args, timeout)?,
+        cli::Command::Pause(_) => wrap::pause(timeout)?,
+        cli::Command::Resume(_) => wrap::resume(timeout)?,
+        cli::Command::Ping(_) => wrap::ping(timeout)?,
+        cli::Command::Logs(args) => log::logs(args)?,
+        cli::Command::Watch(_) => wrap::watch(timeout)?,
     };
 
     Ok(())
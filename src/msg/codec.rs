@@ -0,0 +1,199 @@
+//! frames consist of a header containing the protocol version and the length
+//! of the payload (both little endian), followed by the payload as JSON
+
+use {
+    serde::{de::DeserializeOwned, Serialize},
+    std::{
+        fmt,
+        io::{self, Read, Write},
+    },
+};
+
+pub const PROTOCOL_VERSION: u16 = 2;
+
+/// the largest payload that will be read, to avoid allocating huge buffers
+/// because of a corrupted header
+pub const MAX_PAYLOAD_LEN: u32 = 1 << 20;
+
+const HEADER_LEN: usize = 6;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Version { found: u16 },
+    TooLarge { len: u32 },
+    Json(serde_json::Error),
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Json(e)
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::Io(e) => e,
+            e => io::Error::new(io::ErrorKind::InvalidData, e),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(e) => e.fmt(f),
+            Error::Version { found } => write!(
+                f,
+                "unsupported protocol version {found} (expected {PROTOCOL_VERSION})"
+            ),
+            Error::TooLarge { len } => write!(f, "message too large ({len} bytes)"),
+            Error::Json(e) => write!(f, "invalid message: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+pub fn write<T: Serialize>(mut writer: impl Write, msg: &T) -> Result<(), Error> {
+    let payload = serde_json::to_vec(msg)?;
+    let len = u32::try_from(payload.len())
+        .ok()
+        .filter(|&len| len <= MAX_PAYLOAD_LEN)
+        .ok_or(Error::TooLarge {
+            len: payload.len().try_into().unwrap_or(u32::MAX),
+        })?;
+
+    // written all at once so frames don't end up interleaved
+    let mut frame = Vec::with_capacity(HEADER_LEN + payload.len());
+    frame.extend_from_slice(&PROTOCOL_VERSION.to_le_bytes());
+    frame.extend_from_slice(&len.to_le_bytes());
+    frame.extend_from_slice(&payload);
+
+    writer.write_all(&frame)?;
+    writer.flush().map_err(<_>::into)
+}
+
+/// reads a frame, returning `None` if the stream ended before its start
+pub fn read<T: DeserializeOwned>(mut reader: impl Read) -> Result<Option<T>, Error> {
+    let mut header = [0; HEADER_LEN];
+
+    match reader.read(&mut header[..1])? {
+        0 => return Ok(None),
+        _ => reader.read_exact(&mut header[1..])?,
+    }
+
+    let (version, len) = header.split_at(2);
+    let version = u16::from_le_bytes([version[0], version[1]]);
+    let len = u32::from_le_bytes([len[0], len[1], len[2], len[3]]);
+
+    if version != PROTOCOL_VERSION {
+        return Err(Error::Version { found: version })
+    }
+
+    if len > MAX_PAYLOAD_LEN {
+        return Err(Error::TooLarge { len })
+    }
+
+    let mut payload = vec![0; len as usize];
+    reader.read_exact(&mut payload)?;
+
+    serde_json::from_slice(&payload)
+        .map(Some)
+        .map_err(<_>::into)
+}
+
+#[cfg(test)]
+mod test {
+    use {super::*, serde::Deserialize};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Msg {
+        text: String,
+        number: f64,
+    }
+
+    fn msg() -> Msg {
+        Msg {
+            text: "hello".into(),
+            number: 4.2,
+        }
+    }
+
+    fn frame(version: u16, payload: &[u8]) -> Vec<u8> {
+        let mut frame = version.to_le_bytes().to_vec();
+        frame.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        frame.extend_from_slice(payload);
+        frame
+    }
+
+    #[test]
+    fn write_frame() {
+        let mut buf = Vec::new();
+        write(&mut buf, &msg()).unwrap();
+
+        assert_eq!(
+            buf,
+            frame(PROTOCOL_VERSION, br#"{"text":"hello","number":4.2}"#)
+        )
+    }
+
+    #[test]
+    fn roundtrip() {
+        let mut buf = Vec::new();
+        write(&mut buf, &msg()).unwrap();
+        write(&mut buf, &msg()).unwrap();
+
+        let mut reader = buf.as_slice();
+        assert_eq!(read::<Msg>(&mut reader).unwrap(), Some(msg()));
+        assert_eq!(read::<Msg>(&mut reader).unwrap(), Some(msg()));
+        assert_eq!(read::<Msg>(&mut reader).unwrap(), None)
+    }
+
+    #[test]
+    fn wrong_version() {
+        let frame = frame(PROTOCOL_VERSION + 1, b"{}");
+
+        assert!(matches!(
+            read::<Msg>(frame.as_slice()),
+            Err(Error::Version { found }) if found == PROTOCOL_VERSION + 1
+        ))
+    }
+
+    #[test]
+    fn too_large() {
+        let mut frame = frame(PROTOCOL_VERSION, b"");
+        frame[2..].copy_from_slice(&(MAX_PAYLOAD_LEN + 1).to_le_bytes());
+
+        assert!(matches!(
+            read::<Msg>(frame.as_slice()),
+            Err(Error::TooLarge { len }) if len == MAX_PAYLOAD_LEN + 1
+        ))
+    }
+
+    #[test]
+    fn truncated() {
+        let frame = frame(PROTOCOL_VERSION, br#"{"text":"hello","number":4.2}"#);
+
+        for len in [3, frame.len() - 1] {
+            assert!(matches!(
+                read::<Msg>(&frame[..len]),
+                Err(Error::Io(e)) if e.kind() == io::ErrorKind::UnexpectedEof
+            ))
+        }
+    }
+
+    #[test]
+    fn invalid_payload() {
+        let frame = frame(PROTOCOL_VERSION, br#"{"text":"hello"}"#);
+
+        assert!(matches!(read::<Msg>(frame.as_slice()), Err(Error::Json(_))))
+    }
+}
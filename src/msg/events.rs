@@ -0,0 +1,221 @@
+//! events streamed to subscribed clients. after acknowledging a `subscribe`
+//! request with a `subscribed` message, the server writes one event per line
+//! as JSON until the client disconnects, e.g.
+//!
+//! ```text
+//! {"type":"focus_changed","exe":"C:\\game.exe"}
+//! {"type":"rule_matched","rule":"game.exe","sensitivity":2.0}
+//! {"type":"sensitivity_applied","sensitivity":2.0}
+//! {"type":"config_reloaded"}
+//! {"type":"missed","count":3}
+//! ```
+//!
+//! subscribers that don't keep up miss events instead of slowing down the
+//! server, which is announced with a `missed` event once they've caught up.
+
+use {
+    serde::{Deserialize, Serialize},
+    std::{
+        io::{self, Write},
+        path::PathBuf,
+        sync::{
+            mpsc::{self, Receiver, RecvTimeoutError, SyncSender, TrySendError},
+            Mutex,
+        },
+        time::Duration,
+    },
+};
+
+/// how many events are buffered for each subscriber before they're missed
+const CAPACITY: usize = 64;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    /// `exe` is `None` if the focused process couldn't be determined
+    FocusChanged {
+        exe: Option<PathBuf>,
+    },
+    /// the result of evaluating the config. `rule` is `None` if the default
+    /// sensitivity or an override is used.
+    RuleMatched {
+        rule: Option<PathBuf>,
+        sensitivity: f64,
+    },
+    /// a sensitivity was written to the driver
+    SensitivityApplied {
+        sensitivity: f64,
+    },
+    ConfigReloaded,
+    /// events were dropped because the subscriber didn't keep up
+    Missed {
+        count: usize,
+    },
+}
+
+struct Subscriber {
+    sender: SyncSender<Event>,
+    /// how many events were dropped since the last one that was sent
+    missed: usize,
+}
+
+impl Subscriber {
+    /// returns `false` if the subscription was dropped
+    fn send(&mut self, event: &Event) -> bool {
+        let missed = (self.missed > 0).then_some(Event::Missed { count: self.missed });
+
+        for event in missed.into_iter().chain([event.clone()]) {
+            let is_missed = matches!(event, Event::Missed { .. });
+
+            match self.sender.try_send(event) {
+                Ok(()) if is_missed => self.missed = 0,
+                Ok(()) => {}
+                Err(TrySendError::Full(_)) => {
+                    self.missed += 1;
+                    return true
+                }
+                Err(TrySendError::Disconnected(_)) => return false,
+            }
+        }
+
+        true
+    }
+}
+
+/// a subscription to the events published on a bus
+pub struct Subscription {
+    receiver: Receiver<Event>,
+}
+
+impl Subscription {
+    /// waits for the next event, returning `None` once the bus is dropped
+    pub fn recv(&self) -> Option<Event> {
+        self.receiver.recv().ok()
+    }
+
+    /// like `recv`, but waits for at most `timeout`
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<Event, RecvTimeoutError> {
+        self.receiver.recv_timeout(timeout)
+    }
+
+    /// writes events to `stream` until it's closed
+    pub fn forward(self, mut stream: impl Write) -> io::Result<()> {
+        while let Some(event) = self.recv() {
+            let mut line = serde_json::to_vec(&event)?;
+            line.push(b'\n');
+            stream.write_all(&line)?;
+            stream.flush()?
+        }
+
+        Ok(())
+    }
+}
+
+pub struct Bus {
+    subscribers: Mutex<Vec<Subscriber>>,
+}
+
+impl Bus {
+    pub const fn new() -> Bus {
+        Bus {
+            subscribers: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn subscribe(&self) -> Subscription {
+        let (sender, receiver) = mpsc::sync_channel(CAPACITY);
+
+        self.subscribers
+            .lock()
+            .unwrap()
+            .push(Subscriber { sender, missed: 0 });
+
+        Subscription { receiver }
+    }
+
+    /// sends an event to all subscribers without waiting for any of them
+    pub fn publish(&self, event: Event) {
+        self.subscribers
+            .lock()
+            .unwrap()
+            .retain_mut(|subscriber| subscriber.send(&event))
+    }
+}
+
+static BUS: Bus = Bus::new();
+
+pub fn publish(event: Event) {
+    BUS.publish(event)
+}
+
+pub fn subscribe() -> Subscription {
+    BUS.subscribe()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn applied(sensitivity: f64) -> Event {
+        Event::SensitivityApplied { sensitivity }
+    }
+
+    #[test]
+    fn event_format() {
+        assert_eq!(
+            serde_json::to_string(&Event::RuleMatched {
+                rule: Some("game.exe".into()),
+                sensitivity: 2.
+            })
+            .unwrap(),
+            r#"{"type":"rule_matched","rule":"game.exe","sensitivity":2.0}"#
+        );
+        assert_eq!(
+            serde_json::to_string(&Event::ConfigReloaded).unwrap(),
+            r#"{"type":"config_reloaded"}"#
+        )
+    }
+
+    #[test]
+    fn slow_subscribers_miss_events() {
+        let bus = Bus::new();
+        let subscription = bus.subscribe();
+
+        for i in 0..CAPACITY + 5 {
+            bus.publish(applied(i as f64))
+        }
+
+        for i in 0..CAPACITY {
+            assert_eq!(subscription.recv(), Some(applied(i as f64)))
+        }
+
+        bus.publish(applied(-1.));
+        assert_eq!(subscription.recv(), Some(Event::Missed { count: 5 }));
+        assert_eq!(subscription.recv(), Some(applied(-1.)))
+    }
+
+    #[test]
+    fn disconnected_subscribers_are_removed() {
+        struct Closed;
+
+        impl Write for Closed {
+            fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+                Err(io::ErrorKind::BrokenPipe.into())
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let bus = Bus::new();
+        let (kept, dropped) = (bus.subscribe(), bus.subscribe());
+
+        bus.publish(Event::ConfigReloaded);
+        assert!(dropped.forward(Closed).is_err());
+
+        bus.publish(Event::ConfigReloaded);
+        assert_eq!(bus.subscribers.lock().unwrap().len(), 1);
+        assert_eq!(kept.recv(), Some(Event::ConfigReloaded))
+    }
+}
@@ -0,0 +1,458 @@
+//! an HTTP server on the loopback interface for clients that can't use the
+//! named pipe. every request is answered with JSON and the connection is
+//! closed afterwards, except for events.
+//!
+//! - `GET /status`: the status
+//! - `POST /reload`: reloads the config
+//! - `POST /override` with `{"sensitivity": 2.0, "process": "game.exe",
+//!   "duration_secs": 60}`: overrides the sensitivity, where `process` and
+//!   `duration_secs` are optional
+//! - `DELETE /override`: clears the override
+//! - `GET /events`: streams events as server-sent events, see [`events`]
+//!
+//! requests from browsers are rejected, so websites can't control senscale.
+//!
+//! [`events`]: super::events
+
+use {
+    super::{events, Client, Server},
+    serde::Deserialize,
+    serde_json::json,
+    std::{
+        io::{self, BufRead, BufReader, Read, Write},
+        net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream},
+        path::PathBuf,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            mpsc::RecvTimeoutError,
+            Arc,
+        },
+        thread,
+        time::Duration,
+    },
+};
+
+/// how long clients have to send their request
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+/// the largest request line and headers that will be read
+const MAX_HEAD_LEN: u64 = 8 * 1024;
+/// the largest request body that will be read
+const MAX_BODY_LEN: usize = 64 * 1024;
+/// how many connections are served at once, including event streams
+const MAX_CONNECTIONS: usize = 16;
+/// how long event streams stay quiet before a comment is sent, which frees the
+/// connection once the client is gone
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+#[derive(Deserialize)]
+struct OverrideRequest {
+    sensitivity: f64,
+    process: Option<PathBuf>,
+    duration_secs: Option<u64>,
+}
+
+struct Request {
+    method: String,
+    path: String,
+    host: Option<String>,
+    origin: Option<String>,
+    body: Vec<u8>,
+}
+
+struct Response {
+    status: u16,
+    body: serde_json::Value,
+}
+
+impl Response {
+    fn error(status: u16, message: impl ToString) -> Response {
+        Response {
+            status,
+            body: json!({ "error": message.to_string() }),
+        }
+    }
+}
+
+/// starts serving on `port` in the background, answering requests with
+/// `handle`. returns the address that's listened on.
+pub fn serve(
+    port: u16,
+    handle: impl Fn(Server) -> io::Result<Client> + Send + Sync + 'static,
+) -> io::Result<SocketAddr> {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+    let addr = listener.local_addr()?;
+    let handle = Arc::new(handle);
+    let open = Arc::new(AtomicUsize::new(0));
+
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            // further connections are closed right away
+            let Some(slot) = Slot::take(&open) else {
+                continue
+            };
+
+            let handle = handle.clone();
+
+            // the client may have disconnected already, which is fine
+            thread::spawn(move || {
+                let _slot = slot;
+                connection(stream, addr, &*handle)
+            });
+        }
+    });
+
+    Ok(addr)
+}
+
+/// one of the connections that can be served at once, which is freed when
+/// dropped
+struct Slot(Arc<AtomicUsize>);
+
+impl Slot {
+    fn take(open: &Arc<AtomicUsize>) -> Option<Slot> {
+        open.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |open| {
+            (open < MAX_CONNECTIONS).then_some(open + 1)
+        })
+        .ok()
+        .map(|_| Slot(open.clone()))
+    }
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+fn connection(
+    mut stream: TcpStream,
+    addr: SocketAddr,
+    handle: &dyn Fn(Server) -> io::Result<Client>,
+) -> io::Result<()> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    stream.set_write_timeout(Some(READ_TIMEOUT))?;
+
+    let request = match read_request(&mut stream) {
+        Ok(request) => request,
+        Err(e) => return write_response(stream, Response::error(400, e)),
+    };
+
+    if !is_allowed(&request, addr) {
+        return write_response(stream, Response::error(403, "forbidden"))
+    }
+
+    if (request.method.as_str(), request.path.as_str()) == ("GET", "/events") {
+        return stream_events(stream)
+    }
+
+    write_response(stream, respond(&request, handle))
+}
+
+/// only accepts requests that aren't made by browsers and are addressed to
+/// the loopback interface, to prevent DNS rebinding
+fn is_allowed(request: &Request, addr: SocketAddr) -> bool {
+    let host_allowed = request.host.as_deref().is_some_and(|host| {
+        let name = host
+            .strip_suffix(&format!(":{}", addr.port()))
+            .unwrap_or(host);
+
+        name == "localhost" || name == "127.0.0.1"
+    });
+
+    request.origin.is_none() && host_allowed
+}
+
+fn respond(request: &Request, handle: &dyn Fn(Server) -> io::Result<Client>) -> Response {
+    let msg = match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/status") => Server::Status,
+        ("POST", "/reload") => Server::Reload { print: false },
+        ("POST", "/override") => match serde_json::from_slice::<OverrideRequest>(&request.body) {
+            Ok(body) => match super::valid_sensitivity(body.sensitivity) {
+                Ok(sensitivity) => Server::Set {
+                    sensitivity,
+                    process: body.process,
+                    duration_ms: body.duration_secs.map(|secs| secs.saturating_mul(1000)),
+                },
+                Err(e) => return Response::error(400, format!("invalid override: {e}")),
+            },
+            Err(e) => return Response::error(400, format!("invalid override: {e}")),
+        },
+        ("DELETE", "/override") => Server::ClearOverride,
+        (_, "/status" | "/reload" | "/override" | "/events") => {
+            return Response::error(405, "method not allowed")
+        }
+        _ => return Response::error(404, "not found"),
+    };
+
+    match handle(msg) {
+        Ok(Client::Status(status)) => Response {
+            status: 200,
+            body: json!(status),
+        },
+        Ok(Client::Done { output }) => Response {
+            status: 200,
+            body: json!({ "output": output }),
+        },
+        Ok(Client::Error { message }) => Response::error(500, message),
+        Ok(_) => Response::error(500, "unexpected response"),
+        Err(e) => Response::error(500, e),
+    }
+}
+
+fn read_request(stream: &mut TcpStream) -> io::Result<Request> {
+    let invalid = |msg| io::Error::new(io::ErrorKind::InvalidData, msg);
+    let mut reader = BufReader::new(stream.take(MAX_HEAD_LEN));
+    let mut line = String::new();
+
+    // lines are only cut off if the limit was reached
+    let mut read_line = |line: &mut String| {
+        reader.read_line(line)?;
+
+        if line.ends_with('\n') {
+            Ok(())
+        } else {
+            Err(invalid("request head too large or incomplete"))
+        }
+    };
+
+    read_line(&mut line)?;
+
+    let mut parts = line.split_whitespace();
+    let (method, path) = match (parts.next(), parts.next()) {
+        (Some(method), Some(path)) => (method.to_owned(), path.to_owned()),
+        _ => return Err(invalid("malformed request line")),
+    };
+
+    let mut request = Request {
+        method,
+        path,
+        host: None,
+        origin: None,
+        body: Vec::new(),
+    };
+    let mut len = 0;
+
+    loop {
+        line.clear();
+        read_line(&mut line)?;
+        let line = line.trim_end();
+
+        if line.is_empty() {
+            break
+        }
+
+        let (name, value) = line
+            .split_once(':')
+            .ok_or_else(|| invalid("malformed header"))?;
+        let value = value.trim();
+
+        match name.to_ascii_lowercase().as_str() {
+            "host" => request.host = Some(value.to_owned()),
+            "origin" => request.origin = Some(value.to_owned()),
+            "content-length" => {
+                len = value
+                    .parse()
+                    .map_err(|_| invalid("invalid content length"))?
+            }
+            _ => {}
+        }
+    }
+
+    if len > MAX_BODY_LEN {
+        return Err(invalid("request body too large"))
+    }
+
+    reader.get_mut().set_limit(len as u64);
+    request.body.resize(len, 0);
+    reader.read_exact(&mut request.body)?;
+
+    Ok(request)
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        _ => "Internal Server Error",
+    }
+}
+
+fn write_response(mut stream: TcpStream, response: Response) -> io::Result<()> {
+    let body = response.body.to_string();
+
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: \
+         close\r\n\r\n{body}",
+        response.status,
+        reason(response.status),
+        body.len()
+    )?;
+
+    stream.flush()
+}
+
+/// streams events until the client disconnects
+fn stream_events(mut stream: TcpStream) -> io::Result<()> {
+    let subscription = events::subscribe();
+
+    stream.write_all(
+        b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\n\
+          Connection: close\r\n\r\n",
+    )?;
+
+    loop {
+        match subscription.recv_timeout(KEEPALIVE_INTERVAL) {
+            Ok(event) => {
+                let event = serde_json::to_string(&event)?;
+                stream.write_all(format!("data: {event}\n\n").as_bytes())?
+            }
+            // comments are ignored by clients
+            Err(RecvTimeoutError::Timeout) => stream.write_all(b": keepalive\n\n")?,
+            Err(RecvTimeoutError::Disconnected) => return Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {super::*, crate::msg::Event};
+
+    fn server() -> SocketAddr {
+        serve(0, |msg| {
+            Ok(match msg {
+                Server::Reload { .. } => Client::Done {
+                    output: "reloaded\n".into(),
+                },
+                Server::Set {
+                    sensitivity,
+                    process,
+                    duration_ms,
+                } => Client::Done {
+                    output: format!("{sensitivity} {process:?} {duration_ms:?}"),
+                },
+                _ => Client::Error {
+                    message: "not now".into(),
+                },
+            })
+        })
+        .unwrap()
+    }
+
+    fn send(addr: SocketAddr, request: &str) -> TcpStream {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream
+            .write_all(request.replace("{addr}", &addr.to_string()).as_bytes())
+            .unwrap();
+        stream
+    }
+
+    fn request(addr: SocketAddr, request: &str) -> (String, String) {
+        let mut response = String::new();
+        send(addr, request).read_to_string(&mut response).unwrap();
+
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        let status = head.lines().next().unwrap();
+
+        (status.to_owned(), body.to_owned())
+    }
+
+    #[test]
+    fn requests() {
+        let addr = server();
+
+        assert_eq!(
+            request(addr, "POST /reload HTTP/1.1\r\nHost: {addr}\r\n\r\n"),
+            (
+                "HTTP/1.1 200 OK".into(),
+                r#"{"output":"reloaded\n"}"#.into()
+            )
+        );
+
+        let body = r#"{"sensitivity":2.5,"process":"game.exe","duration_secs":2}"#;
+        assert_eq!(
+            request(
+                addr,
+                &format!(
+                    "POST /override HTTP/1.1\r\nHost: {{addr}}\r\nContent-Length: {}\r\n\r\n{body}",
+                    body.len()
+                )
+            ),
+            (
+                "HTTP/1.1 200 OK".into(),
+                r#"{"output":"2.5 Some(\"game.exe\") Some(2000)"}"#.into()
+            )
+        );
+
+        assert_eq!(
+            request(addr, "GET /status HTTP/1.1\r\nHost: localhost\r\n\r\n"),
+            (
+                "HTTP/1.1 500 Internal Server Error".into(),
+                r#"{"error":"not now"}"#.into()
+            )
+        )
+    }
+
+    #[test]
+    fn rejected_requests() {
+        let addr = server();
+        let status = |text: &str| request(addr, text).0;
+
+        assert_eq!(
+            status("GET /reload HTTP/1.1\r\nHost: {addr}\r\n\r\n"),
+            "HTTP/1.1 405 Method Not Allowed"
+        );
+        assert_eq!(
+            status("GET /nothing HTTP/1.1\r\nHost: {addr}\r\n\r\n"),
+            "HTTP/1.1 404 Not Found"
+        );
+        assert_eq!(
+            status("POST /override HTTP/1.1\r\nHost: {addr}\r\nContent-Length: 2\r\n\r\n{}"),
+            "HTTP/1.1 400 Bad Request"
+        );
+        assert_eq!(
+            status(
+                "POST /override HTTP/1.1\r\nHost: {addr}\r\nContent-Length: \
+                 17\r\n\r\n{\"sensitivity\":0}"
+            ),
+            "HTTP/1.1 400 Bad Request"
+        );
+        assert_eq!(
+            status("POST /reload HTTP/1.1\r\nHost: {addr}\r\nOrigin: https://example.com\r\n\r\n"),
+            "HTTP/1.1 403 Forbidden"
+        );
+        assert_eq!(
+            status("POST /reload HTTP/1.1\r\nHost: example.com\r\n\r\n"),
+            "HTTP/1.1 403 Forbidden"
+        );
+        assert_eq!(status("nonsense\r\n\r\n"), "HTTP/1.1 400 Bad Request");
+
+        // the whole request is read, as closing the connection with unread data
+        // resets it
+        let mut long = "GET /status HTTP/1.1\r\nX-Padding: ".to_owned();
+        long.extend(std::iter::repeat_n('a', MAX_HEAD_LEN as usize - long.len()));
+        assert_eq!(status(&long), "HTTP/1.1 400 Bad Request")
+    }
+
+    #[test]
+    fn server_sent_events() {
+        let addr = server();
+        let mut reader = BufReader::new(send(addr, "GET /events HTTP/1.1\r\nHost: {addr}\r\n\r\n"));
+        let mut line = String::new();
+
+        // the client is subscribed once the headers are sent
+        while line != "\r\n" {
+            line.clear();
+            reader.read_line(&mut line).unwrap();
+        }
+
+        events::publish(Event::ConfigReloaded);
+
+        line.clear();
+        reader.read_line(&mut line).unwrap();
+        assert_eq!(line, "data: {\"type\":\"config_reloaded\"}\n")
+    }
+}
@@ -0,0 +1,635 @@
+use {
+    crate::{output::report, thread_id::ThreadId, windows::thread},
+    serde::{Deserialize, Serialize},
+    std::{
+        io::{self, BufRead, BufReader},
+        path::PathBuf,
+        sync::mpsc::{self, Receiver, Sender},
+        thread as std_thread,
+        time::{Duration, Instant},
+    },
+    transport::Transport,
+};
+
+pub use {
+    events::{publish, Event},
+    status::{unix_secs, HumanDuration, OverrideStatus, Source, Status},
+    transport::default_name,
+};
+
+pub mod codec;
+pub mod events;
+pub mod http;
+mod queue;
+mod status;
+mod transport;
+
+/// messages sent to the server
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Server {
+    Stop,
+    Reload {
+        print: bool,
+    },
+    Status,
+    /// overrides the sensitivity, optionally only for one process or for a
+    /// limited time
+    Set {
+        sensitivity: f64,
+        process: Option<PathBuf>,
+        duration_ms: Option<u64>,
+    },
+    ClearOverride,
+    /// applies the default sensitivity and ignores events until resumed
+    Pause,
+    Resume,
+    Ping,
+    /// takes the output that wasn't sent in response to a request
+    TakeOutput,
+    /// streams events until the client disconnects, see [`events`]
+    Subscribe,
+}
+
+/// messages sent to the client
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Client {
+    Stopped,
+    Pong,
+    /// the request was handled, along with the output produced while doing so
+    Done {
+        output: String,
+    },
+    Status(Status),
+    /// events follow, one per line
+    Subscribed,
+    Error {
+        message: String,
+    },
+}
+
+/// sends a message to the server listening on `name` and waits for the
+/// response for at most `timeout`
+pub fn request(name: &str, msg: &Server, timeout: Duration) -> io::Result<Client> {
+    request_via(transport::Local, name, msg, timeout)
+}
+
+/// checks whether the server listening on `name` responds and returns how long
+/// that took
+pub fn ping(name: &str, timeout: Duration) -> io::Result<Duration> {
+    let start = Instant::now();
+
+    match request(name, &Server::Ping, timeout)? {
+        Client::Pong => Ok(start.elapsed()),
+        _ => Err(unexpected_response()),
+    }
+}
+
+pub fn unexpected_response() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        "unexpected response from the running instance",
+    )
+}
+
+/// subscribes to the events of the server listening on `name`, waiting for at
+/// most `timeout` for it to accept. returns the events as lines of JSON.
+pub fn subscribe(
+    name: &str,
+    timeout: Duration,
+) -> io::Result<impl Iterator<Item = io::Result<String>>> {
+    subscribe_via(transport::Local, name, timeout)
+}
+
+fn request_via<T: Transport + Send + 'static>(
+    transport: T,
+    name: &str,
+    msg: &Server,
+    timeout: Duration,
+) -> io::Result<Client> {
+    let (name, msg) = (name.to_owned(), msg.clone());
+
+    with_timeout(timeout, move || {
+        exchange(&transport, &name, &msg).map(|(_, response)| response)
+    })
+}
+
+fn subscribe_via<T: Transport + Send + 'static>(
+    transport: T,
+    name: &str,
+    timeout: Duration,
+) -> io::Result<io::Lines<BufReader<T::Stream>>> {
+    let name = name.to_owned();
+
+    let stream = with_timeout(timeout, move || {
+        match exchange(&transport, &name, &Server::Subscribe)? {
+            (stream, Client::Subscribed) => Ok(stream),
+            _ => Err(unexpected_response()),
+        }
+    })?;
+
+    Ok(BufReader::new(stream).lines())
+}
+
+/// reads can't time out on every platform, so requests are made on a separate
+/// thread that's abandoned if it takes too long
+fn with_timeout<T: Send + 'static>(
+    timeout: Duration,
+    f: impl FnOnce() -> io::Result<T> + Send + 'static,
+) -> io::Result<T> {
+    let (sender, receiver) = mpsc::channel();
+
+    std_thread::spawn(move || {
+        let _ = sender.send(f());
+    });
+
+    receiver.recv_timeout(timeout).unwrap_or_else(|_| {
+        Err(io::Error::new(
+            io::ErrorKind::TimedOut,
+            "daemon not responding",
+        ))
+    })
+}
+
+fn exchange<T: Transport>(
+    transport: &T,
+    name: &str,
+    msg: &Server,
+) -> io::Result<(T::Stream, Client)> {
+    let mut stream = transport.connect(name).map_err(connect_error)?;
+    codec::write(&mut stream, msg)?;
+
+    match codec::read(&mut stream)? {
+        Some(Client::Error { message }) => Err(io::Error::other(message)),
+        Some(response) => Ok((stream, response)),
+        None => Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "no response from the running instance",
+        )),
+    }
+}
+
+/// checks whether a server is listening on `name`
+pub fn is_listening(name: &str) -> bool {
+    transport::connect(name).is_ok()
+}
+
+fn connect_error(err: io::Error) -> io::Error {
+    if err.kind() == io::ErrorKind::NotFound {
+        io::Error::new(err.kind(), "no instance running")
+    } else {
+        err
+    }
+}
+
+/// where the reply to a request goes
+enum Origin {
+    /// the thread the client connected on, which writes the reply
+    Connection(Sender<Reply>),
+    /// the request was submitted from within the server
+    Channel(Sender<Client>),
+}
+
+/// what the thread a client connected on does with the connection
+enum Reply {
+    Client(Client),
+    Subscribe(events::Subscription),
+}
+
+/// a request received by the server, which should be replied to
+pub struct Request {
+    pub msg: Server,
+    origin: Origin,
+}
+
+impl Request {
+    pub fn reply(self, msg: Client) {
+        // the client may have disconnected already, which is fine
+        match self.origin {
+            Origin::Connection(sender) => {
+                let _ = sender.send(Reply::Client(msg));
+            }
+            Origin::Channel(sender) => {
+                let _ = sender.send(msg);
+            }
+        }
+    }
+
+    /// accepts a subscription and streams events to the client on the thread
+    /// it connected on until it disconnects
+    pub fn subscribe(self) {
+        match self.origin {
+            // subscribed here so no events are missed in between
+            Origin::Connection(sender) => {
+                let _ = sender.send(Reply::Subscribe(events::subscribe()));
+            }
+            Origin::Channel(sender) => {
+                let _ = sender.send(Client::Error {
+                    message: "can't subscribe from within the server".into(),
+                });
+            }
+        }
+    }
+}
+
+/// checks that a sensitivity from a request can be applied, which it can't if
+/// it isn't a positive number
+pub fn valid_sensitivity(sensitivity: f64) -> Result<f64, String> {
+    if sensitivity.is_finite() && sensitivity > 0. {
+        Ok(sensitivity)
+    } else {
+        Err(format!(
+            "invalid sensitivity {sensitivity}, it has to be a positive number"
+        ))
+    }
+}
+
+/// something the thread that listens was woken up for
+pub enum Incoming {
+    Request(Request),
+    /// the hotkey with the given id was pressed
+    Hotkey(i32),
+}
+
+pub struct Requests {
+    receiver: Receiver<Request>,
+    submitter: Submitter,
+}
+
+/// submits requests to be received along with the ones from clients
+#[derive(Clone)]
+pub struct Submitter {
+    sender: Sender<Request>,
+    thread: ThreadId,
+}
+
+impl Submitter {
+    fn send(&self, request: Request) -> bool {
+        let sent = self.sender.send(request).is_ok();
+
+        if sent {
+            let _ = queue::wake(self.thread);
+        }
+
+        sent
+    }
+
+    /// submits a request and waits for the reply for at most `timeout`
+    pub fn submit(&self, msg: Server, timeout: Duration) -> io::Result<Client> {
+        let (sender, receiver) = mpsc::channel();
+
+        if !self.send(Request {
+            msg,
+            origin: Origin::Channel(sender),
+        }) {
+            return Err(io::Error::new(
+                io::ErrorKind::BrokenPipe,
+                "requests aren't received anymore",
+            ))
+        }
+
+        match receiver.recv_timeout(timeout) {
+            Ok(Client::Error { message }) => Err(io::Error::other(message)),
+            Ok(response) => Ok(response),
+            Err(_) => Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "request wasn't handled in time",
+            )),
+        }
+    }
+}
+
+/// starts listening for requests on `name` in the background. the requests are
+/// received on the current thread by iterating over them, which also pumps the
+/// thread's message queue.
+pub fn listen(name: &str) -> io::Result<Requests> {
+    queue::create();
+
+    let mut listener = transport::Listener::bind(name)?;
+    let (sender, receiver) = mpsc::channel();
+    let submitter = Submitter {
+        sender,
+        thread: thread::current_id(),
+    };
+
+    let accepted = submitter.clone();
+
+    std_thread::spawn(move || loop {
+        match listener.accept() {
+            Ok(stream) => {
+                let submitter = accepted.clone();
+                std_thread::spawn(move || receive(stream, submitter));
+            }
+            Err(e) => {
+                report!(Error, General, "couldn't accept connections: {e}");
+                break
+            }
+        }
+    });
+
+    Ok(Requests {
+        receiver,
+        submitter,
+    })
+}
+
+/// reads a request and writes its reply on a separate thread, so clients that
+/// are slow to send or read them don't block the receiving thread
+fn receive(mut stream: transport::Stream, submitter: Submitter) {
+    match codec::read(&mut stream) {
+        Ok(Some(msg)) => {
+            let (sender, receiver) = mpsc::channel();
+
+            if !submitter.send(Request {
+                msg,
+                origin: Origin::Connection(sender),
+            }) {
+                return
+            }
+
+            match receiver.recv() {
+                Ok(Reply::Client(msg)) => {
+                    if codec::write(&mut stream, &msg).is_ok() {
+                        let _ = transport::flush(&stream);
+                    }
+                }
+                Ok(Reply::Subscribe(subscription)) => {
+                    if codec::write(&mut stream, &Client::Subscribed).is_ok() {
+                        let _ = subscription.forward(stream);
+                    }
+                }
+                // the request was dropped without a reply
+                Err(_) => {}
+            }
+        }
+        Ok(None) => {}
+        Err(e) => {
+            let _ = codec::write(&mut stream, &Client::Error {
+                message: e.to_string(),
+            });
+        }
+    }
+}
+
+impl Requests {
+    pub fn submitter(&self) -> Submitter {
+        self.submitter.clone()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Incoming> + '_ {
+        std::iter::from_fn(move || loop {
+            if let Ok(request) = self.receiver.try_recv() {
+                return Some(Incoming::Request(request))
+            }
+
+            if let queue::Woken::Hotkey(id) = queue::wait()? {
+                return Some(Incoming::Hotkey(id))
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        std::io::{Read, Write},
+    };
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    fn received(requests: &Requests) -> impl Iterator<Item = Request> + '_ {
+        requests.iter().filter_map(|incoming| match incoming {
+            Incoming::Request(request) => Some(request),
+            Incoming::Hotkey(_) => None,
+        })
+    }
+
+    /// a server that behaves in a predetermined way
+    #[derive(Clone, Copy)]
+    enum FakeServer {
+        Missing,
+        Replies,
+        Hangs,
+        Disconnects,
+    }
+
+    struct FakeStream {
+        server: FakeServer,
+        response: io::Cursor<Vec<u8>>,
+    }
+
+    impl Transport for FakeServer {
+        type Stream = FakeStream;
+
+        fn connect(&self, _name: &str) -> io::Result<FakeStream> {
+            let mut response = Vec::new();
+
+            match self {
+                FakeServer::Missing => return Err(io::ErrorKind::NotFound.into()),
+                FakeServer::Replies => codec::write(&mut response, &Client::Pong)?,
+                _ => {}
+            }
+
+            Ok(FakeStream {
+                server: *self,
+                response: io::Cursor::new(response),
+            })
+        }
+    }
+
+    impl Read for FakeStream {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if let FakeServer::Hangs = self.server {
+                std_thread::park()
+            }
+
+            self.response.read(buf)
+        }
+    }
+
+    impl Write for FakeStream {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn fake_request(server: FakeServer, timeout: Duration) -> io::Result<Client> {
+        request_via(server, "fake", &Server::Ping, timeout)
+    }
+
+    #[test]
+    fn timeouts() {
+        assert_eq!(
+            fake_request(FakeServer::Replies, TIMEOUT).unwrap(),
+            Client::Pong
+        );
+
+        let err = fake_request(FakeServer::Hangs, Duration::from_millis(10)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert_eq!(err.to_string(), "daemon not responding");
+
+        let err = fake_request(FakeServer::Disconnects, TIMEOUT).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+
+        let err = fake_request(FakeServer::Missing, TIMEOUT).unwrap_err();
+        assert_eq!(err.to_string(), "no instance running")
+    }
+
+    #[test]
+    fn message_format() {
+        assert_eq!(
+            serde_json::to_string(&Server::Reload { print: true }).unwrap(),
+            r#"{"type":"reload","print":true}"#
+        );
+        assert_eq!(
+            serde_json::from_str::<Client>(r#"{"type":"error","message":"oops"}"#).unwrap(),
+            Client::Error {
+                message: "oops".into()
+            }
+        )
+    }
+
+    #[test]
+    fn request_and_reply() {
+        let name = format!("senscale-test-{}", std::process::id());
+        let requests = listen(&name).unwrap();
+
+        let client = {
+            let name = name.clone();
+            std_thread::spawn(move || {
+                (
+                    request(&name, &Server::Reload { print: false }, TIMEOUT).unwrap(),
+                    request(&name, &Server::Stop, TIMEOUT)
+                        .unwrap_err()
+                        .to_string(),
+                )
+            })
+        };
+
+        let mut requests = received(&requests);
+
+        let reload = requests.next().unwrap();
+        assert_eq!(reload.msg, Server::Reload { print: false });
+        reload.reply(Client::Done {
+            output: "reloaded\n".into(),
+        });
+
+        let stop = requests.next().unwrap();
+        assert_eq!(stop.msg, Server::Stop);
+        stop.reply(Client::Error {
+            message: "not now".into(),
+        });
+
+        assert_eq!(
+            client.join().unwrap(),
+            (
+                Client::Done {
+                    output: "reloaded\n".into()
+                },
+                "not now".into()
+            )
+        );
+
+        assert!(!is_listening("senscale-test-nonexistent"))
+    }
+
+    #[test]
+    fn submitted_requests() {
+        let name = format!("senscale-test-{}-submitted", std::process::id());
+        let requests = listen(&name).unwrap();
+        let submitter = requests.submitter();
+
+        let client = std_thread::spawn(move || {
+            (
+                submitter.submit(Server::Ping, TIMEOUT).unwrap(),
+                submitter
+                    .submit(Server::Subscribe, TIMEOUT)
+                    .unwrap_err()
+                    .to_string(),
+            )
+        });
+
+        let mut requests = received(&requests);
+        requests.next().unwrap().reply(Client::Pong);
+        requests.next().unwrap().subscribe();
+
+        assert_eq!(
+            client.join().unwrap(),
+            (
+                Client::Pong,
+                "can't subscribe from within the server".into()
+            )
+        )
+    }
+
+    #[test]
+    fn subscription() {
+        let name = format!("senscale-test-{}-subscription", std::process::id());
+        let requests = listen(&name).unwrap();
+
+        let client = {
+            let name = name.clone();
+            std_thread::spawn(move || {
+                subscribe(&name, TIMEOUT)
+                    .unwrap()
+                    .take(2)
+                    .collect::<io::Result<Vec<_>>>()
+                    .unwrap()
+            })
+        };
+
+        let request = received(&requests).next().unwrap();
+        assert_eq!(request.msg, Server::Subscribe);
+        request.subscribe();
+
+        publish(Event::ConfigReloaded);
+        publish(Event::ConfigReloaded);
+
+        assert_eq!(client.join().unwrap(), [
+            r#"{"type":"config_reloaded"}"#,
+            r#"{"type":"config_reloaded"}"#
+        ])
+    }
+
+    #[test]
+    fn concurrent_clients() {
+        let name = format!("senscale-test-{}-concurrent", std::process::id());
+        let requests = listen(&name).unwrap();
+
+        let clients = (0..4)
+            .map(|i| {
+                let name = name.clone();
+                let msg = Server::Set {
+                    sensitivity: i as f64 + 1.,
+                    process: None,
+                    duration_ms: None,
+                };
+
+                std_thread::spawn(move || (i, request(&name, &msg, TIMEOUT).unwrap()))
+            })
+            .collect::<Vec<_>>();
+
+        for request in received(&requests).take(clients.len()) {
+            let output = match &request.msg {
+                Server::Set { sensitivity, .. } => format!("set {sensitivity}\n"),
+                msg => panic!("unexpected request: {msg:?}"),
+            };
+
+            request.reply(Client::Done { output })
+        }
+
+        for client in clients {
+            let (i, response) = client.join().unwrap();
+
+            assert_eq!(response, Client::Done {
+                output: format!("set {}\n", i + 1)
+            })
+        }
+    }
+}
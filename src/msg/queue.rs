@@ -0,0 +1,60 @@
+//! the thread message queue, which has to be pumped for the hooks to work and
+//! is used to wake up the thread when a request arrives or a hotkey is pressed
+
+use {
+    crate::{thread_id::ThreadId, windows::util::validate},
+    std::{io, mem::MaybeUninit, ptr},
+    winapi::um::winuser::{
+        DispatchMessageA,
+        GetMessageA,
+        PeekMessageA,
+        PostThreadMessageA,
+        TranslateMessage,
+        PM_NOREMOVE,
+        WM_APP,
+        WM_HOTKEY,
+    },
+};
+
+const WM_WAKE: u32 = WM_APP;
+
+/// why the thread was woken up
+pub enum Woken {
+    Request,
+    /// the hotkey with the given id was pressed
+    Hotkey(i32),
+}
+
+/// makes sure the current thread has a message queue, so messages can be
+/// posted to it
+pub fn create() {
+    let mut msg = MaybeUninit::uninit();
+    unsafe { PeekMessageA(msg.as_mut_ptr(), ptr::null_mut(), 0, 0, PM_NOREMOVE) };
+}
+
+pub fn wake(thread: ThreadId) -> io::Result<()> {
+    validate(unsafe { PostThreadMessageA(thread.into(), WM_WAKE, 0, 0) })
+}
+
+/// dispatches messages until the thread is woken up, returning `None` if the
+/// queue was closed instead
+pub fn wait() -> Option<Woken> {
+    let mut msg = MaybeUninit::uninit();
+
+    while unsafe { GetMessageA(msg.as_mut_ptr(), ptr::null_mut(), 0, 0) } > 0 {
+        let msg = unsafe { msg.assume_init_ref() };
+
+        match msg.message {
+            WM_WAKE => return Some(Woken::Request),
+            WM_HOTKEY => return Some(Woken::Hotkey(msg.wParam as i32)),
+            _ => {}
+        }
+
+        unsafe {
+            TranslateMessage(msg);
+            DispatchMessageA(msg);
+        }
+    }
+
+    None
+}
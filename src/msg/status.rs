@@ -0,0 +1,234 @@
+use {
+    serde::{Deserialize, Serialize},
+    std::{
+        fmt,
+        path::PathBuf,
+        time::{Duration, SystemTime, UNIX_EPOCH},
+    },
+};
+
+/// what the running instance is currently doing
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Status {
+    pub paused: bool,
+    /// the exe of the focused process, if any
+    pub focus: Option<PathBuf>,
+    pub cursor_hidden: bool,
+    /// the key of the config entry that was last applied, if any
+    pub rule: Option<PathBuf>,
+    /// where the last applied sensitivity came from, if any was applied yet
+    pub source: Option<Source>,
+    /// the sensitivity that was last written to the driver
+    pub sensitivity: Option<f64>,
+    pub config_path: Option<PathBuf>,
+    /// seconds since the unix epoch
+    pub config_loaded_at: u64,
+    pub uptime_secs: u64,
+    /// the error that occurred when last writing to the driver
+    pub driver_error: Option<String>,
+    #[serde(rename = "override")]
+    pub forced: Option<OverrideStatus>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Source {
+    Entry,
+    /// the entry's `while_held` sensitivity
+    Held,
+    Script,
+    Override,
+    /// the default sensitivity, which also applies while paused or idle
+    Default,
+    Desktop,
+    Unmatched,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Source::Entry => "from the entry",
+            Source::Held => "while held",
+            Source::Script => "from the script",
+            Source::Override => "overridden",
+            Source::Default => "default sensitivity",
+            Source::Desktop => "desktop sensitivity",
+            Source::Unmatched => "unmatched sensitivity",
+        }
+        .fmt(f)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OverrideStatus {
+    pub sensitivity: f64,
+    pub process: Option<PathBuf>,
+    pub expires_in_secs: Option<u64>,
+}
+
+pub fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+impl Status {
+    fn fmt_at(&self, f: &mut fmt::Formatter, now: SystemTime) -> fmt::Result {
+        if self.paused {
+            writeln!(f, "paused, using the default sensitivity")?
+        }
+
+        match &self.focus {
+            Some(exe) => writeln!(f, "focused: {}", exe.display())?,
+            None => writeln!(f, "focused: nothing")?,
+        }
+
+        writeln!(f, "cursor hidden: {}", self.cursor_hidden)?;
+
+        match (&self.rule, self.source) {
+            (Some(key), Some(Source::Held)) => {
+                writeln!(f, "entry: {} (while held)", key.display())?
+            }
+            (Some(key), _) => writeln!(f, "entry: {}", key.display())?,
+            (None, Some(source)) => writeln!(f, "entry: none ({source})")?,
+            (None, None) => writeln!(f, "entry: none")?,
+        }
+
+        match self.sensitivity {
+            Some(sens) => writeln!(f, "sensitivity: {sens}")?,
+            None => writeln!(f, "sensitivity: not set yet")?,
+        }
+
+        if let Some(forced) = &self.forced {
+            write!(f, "override: {}", forced.sensitivity)?;
+
+            if let Some(process) = &forced.process {
+                write!(f, " for {}", process.display())?
+            }
+
+            match forced.expires_in_secs {
+                Some(secs) => writeln!(
+                    f,
+                    " (expires in {})",
+                    HumanDuration(Duration::from_secs(secs))
+                )?,
+                None => writeln!(f)?,
+            }
+        }
+
+        if let Some(path) = &self.config_path {
+            writeln!(f, "config: {}", path.display())?
+        }
+
+        let loaded = unix_secs(now).saturating_sub(self.config_loaded_at);
+        writeln!(
+            f,
+            "config loaded: {} ago",
+            HumanDuration(Duration::from_secs(loaded))
+        )?;
+        writeln!(
+            f,
+            "uptime: {}",
+            HumanDuration(Duration::from_secs(self.uptime_secs))
+        )?;
+
+        match &self.driver_error {
+            Some(e) => writeln!(f, "driver: {e}"),
+            None => writeln!(f, "driver: ok"),
+        }
+    }
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.fmt_at(f, SystemTime::now())
+    }
+}
+
+pub struct HumanDuration(pub Duration);
+
+impl fmt::Display for HumanDuration {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let secs = self.0.as_secs();
+        let (hours, mins, secs) = (secs / 3600, secs / 60 % 60, secs % 60);
+
+        if hours > 0 {
+            write!(f, "{hours}h {mins}m {secs}s")
+        } else if mins > 0 {
+            write!(f, "{mins}m {secs}s")
+        } else if secs > 0 || self.0.is_zero() {
+            write!(f, "{secs}s")
+        } else {
+            write!(f, "{}ms", self.0.as_millis())
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct At<'a>(&'a Status, SystemTime);
+
+    impl fmt::Display for At<'_> {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            self.0.fmt_at(f, self.1)
+        }
+    }
+
+    #[test]
+    fn status_text() {
+        let status = Status {
+            paused: false,
+            focus: Some(r"C:\game.exe".into()),
+            cursor_hidden: true,
+            rule: Some("game.exe".into()),
+            source: Some(Source::Held),
+            sensitivity: Some(0.5),
+            config_path: Some(r"C:\config.yaml".into()),
+            config_loaded_at: 1000,
+            uptime_secs: 3725,
+            driver_error: None,
+            forced: Some(OverrideStatus {
+                sensitivity: 2.,
+                process: Some("game.exe".into()),
+                expires_in_secs: Some(61),
+            }),
+        };
+
+        assert_eq!(
+            At(&status, UNIX_EPOCH + Duration::from_secs(1090)).to_string(),
+            "focused: C:\\game.exe
+cursor hidden: true
+entry: game.exe (while held)
+sensitivity: 0.5
+override: 2 for game.exe (expires in 1m 1s)
+config: C:\\config.yaml
+config loaded: 1m 30s ago
+uptime: 1h 2m 5s
+driver: ok
+"
+        );
+
+        let status = Status {
+            focus: None,
+            rule: None,
+            source: Some(Source::Default),
+            sensitivity: None,
+            driver_error: Some("RawAccel driver not installed".into()),
+            forced: None,
+            paused: true,
+            ..status
+        };
+
+        assert!(At(&status, UNIX_EPOCH)
+            .to_string()
+            .starts_with("paused, using the default sensitivity\nfocused: nothing\n"));
+        assert!(At(&status, UNIX_EPOCH)
+            .to_string()
+            .contains("entry: none (default sensitivity)\nsensitivity: not set yet\n"));
+        assert!(At(&status, UNIX_EPOCH).to_string().ends_with(
+            "config loaded: 0s ago\nuptime: 1h 2m 5s\ndriver: RawAccel driver not installed\n"
+        ));
+    }
+}
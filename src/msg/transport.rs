@@ -0,0 +1,148 @@
+use {
+    crate::windows::{process, util::validate},
+    std::{
+        ffi::OsStr,
+        fs::{File, OpenOptions},
+        io,
+        mem,
+        os::windows::{
+            ffi::OsStrExt,
+            io::{AsRawHandle, FromRawHandle},
+        },
+        ptr,
+    },
+    winapi::{
+        shared::winerror::{ERROR_PIPE_BUSY, ERROR_PIPE_CONNECTED},
+        um::{
+            handleapi::INVALID_HANDLE_VALUE,
+            namedpipeapi::{ConnectNamedPipe, CreateNamedPipeW, WaitNamedPipeW},
+            winbase::{
+                FILE_FLAG_FIRST_PIPE_INSTANCE,
+                PIPE_ACCESS_DUPLEX,
+                PIPE_READMODE_BYTE,
+                PIPE_REJECT_REMOTE_CLIENTS,
+                PIPE_TYPE_BYTE,
+                PIPE_UNLIMITED_INSTANCES,
+                PIPE_WAIT,
+            },
+        },
+    },
+};
+
+/// a way of connecting to a server
+pub trait Transport {
+    type Stream: io::Read + io::Write + Send + 'static;
+
+    fn connect(&self, name: &str) -> io::Result<Self::Stream>;
+}
+
+/// connects to servers on the same machine using named pipes
+pub struct Local;
+
+impl Transport for Local {
+    type Stream = Stream;
+
+    fn connect(&self, name: &str) -> io::Result<Stream> {
+        connect(name)
+    }
+}
+
+pub type Stream = File;
+
+/// the name instances listen on, which is specific to the current user and
+/// session, since pipe names are shared by all of them
+pub fn default_name() -> io::Result<String> {
+    let session = process::session_id(std::process::id())?;
+    let user = process::current_user_sid()?;
+
+    Ok(format!("senscale-{session}-{user}"))
+}
+
+const BUFFER_SIZE: u32 = 4096;
+const BUSY_WAIT_MILLIS: u32 = 1000;
+
+fn path(name: &str) -> String {
+    format!(r"\\.\pipe\{name}")
+}
+
+/// a named pipe server that accepts one client per pipe instance
+pub struct Listener {
+    path: Vec<u16>,
+    /// the instance the next client will connect to
+    pending: File,
+}
+
+impl Listener {
+    /// creates the pipe, failing if another process already created one
+    /// with the same name
+    pub fn bind(name: &str) -> io::Result<Listener> {
+        let path = OsStr::new(&path(name))
+            .encode_wide()
+            .chain([0])
+            .collect::<Vec<_>>();
+        let pending = create_instance(&path, FILE_FLAG_FIRST_PIPE_INSTANCE)?;
+
+        Ok(Listener { path, pending })
+    }
+
+    /// waits for a client to connect
+    pub fn accept(&mut self) -> io::Result<Stream> {
+        let res = validate(unsafe {
+            ConnectNamedPipe(self.pending.as_raw_handle() as _, ptr::null_mut())
+        });
+
+        match res {
+            // the client connected before we started waiting
+            Err(e) if e.raw_os_error() == Some(ERROR_PIPE_CONNECTED as _) => {}
+            res => res?,
+        }
+
+        let next = create_instance(&self.path, 0)?;
+        Ok(mem::replace(&mut self.pending, next))
+    }
+}
+
+fn create_instance(path: &[u16], flags: u32) -> io::Result<File> {
+    let handle = unsafe {
+        CreateNamedPipeW(
+            path.as_ptr(),
+            PIPE_ACCESS_DUPLEX | flags,
+            PIPE_TYPE_BYTE | PIPE_READMODE_BYTE | PIPE_WAIT | PIPE_REJECT_REMOTE_CLIENTS,
+            PIPE_UNLIMITED_INSTANCES,
+            BUFFER_SIZE,
+            BUFFER_SIZE,
+            0,
+            ptr::null_mut(),
+        )
+    };
+
+    if handle == INVALID_HANDLE_VALUE {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(unsafe { File::from_raw_handle(handle as _) })
+    }
+}
+
+/// waits for the client to read everything that was written, since unread
+/// data may be discarded once the pipe is closed
+pub fn flush(stream: &Stream) -> io::Result<()> {
+    stream.sync_all()
+}
+
+pub fn connect(name: &str) -> io::Result<Stream> {
+    let path = path(name);
+
+    loop {
+        match OpenOptions::new().read(true).write(true).open(&path) {
+            // all instances are in use, so we wait for one to become available
+            Err(e) if e.raw_os_error() == Some(ERROR_PIPE_BUSY as _) => {
+                let wide = OsStr::new(&path)
+                    .encode_wide()
+                    .chain([0])
+                    .collect::<Vec<_>>();
+                validate(unsafe { WaitNamedPipeW(wide.as_ptr(), BUSY_WAIT_MILLIS) })?
+            }
+            res => return res,
+        }
+    }
+}
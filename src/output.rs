@@ -0,0 +1,276 @@
+//! output of the running instance. output produced while handling a request
+//! is sent to the client that made it, while everything else is kept until a
+//! client asks for it. all output is also written to stderr (or the output
+//! file), while the log receives everything, including messages below the
+//! output level.
+
+use {
+    crate::log::{Kind, Level, Record},
+    std::{cell::RefCell, fmt, io::Write, sync::Mutex},
+};
+
+/// the most output that's kept for clients, after which the oldest lines are
+/// discarded
+const MAX_PENDING_LEN: usize = 64 * 1024;
+
+thread_local! {
+    static CAPTURED: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// reports a line of output with the given level and kind, e.g.
+/// `report!(Warn, Driver, "...")`
+macro_rules! report {
+    ($level:ident, $kind:ident, $($arg:tt)*) => {
+        $crate::output::report_args(
+            $crate::log::Level::$level,
+            $crate::log::Kind::$kind,
+            format_args!($($arg)*),
+        )
+    };
+}
+
+pub(crate) use report;
+
+type Log = Box<dyn Write + Send>;
+
+pub struct Sink {
+    pending: Mutex<String>,
+    log: Mutex<Option<Log>>,
+    output: Mutex<Option<Log>>,
+    level: Mutex<Level>,
+}
+
+impl Sink {
+    pub const fn new() -> Sink {
+        Sink {
+            pending: Mutex::new(String::new()),
+            log: Mutex::new(None),
+            output: Mutex::new(None),
+            level: Mutex::new(Level::Info),
+        }
+    }
+
+    pub fn log_to(&self, log: Log) {
+        self.log.lock().unwrap().replace(log);
+    }
+
+    pub fn output_to(&self, output: Log) {
+        self.output.lock().unwrap().replace(output);
+    }
+
+    /// sets the lowest level of messages that are output
+    pub fn set_level(&self, level: Level) {
+        *self.level.lock().unwrap() = level
+    }
+
+    pub fn report(&self, level: Level, kind: Kind, args: fmt::Arguments) {
+        let record = Record::now(level, kind, args.to_string());
+
+        // written at once, so a line never ends up in two files when the log
+        // is rotated. there's nowhere to report errors to.
+        if let Some(log) = self.log.lock().unwrap().as_mut() {
+            let _ = log.write_all(format!("{}\n", record.to_json()).as_bytes());
+        }
+
+        if level < *self.level.lock().unwrap() {
+            return
+        }
+
+        let line = format!("{}\n", record.message);
+
+        match self.output.lock().unwrap().as_mut() {
+            Some(output) => {
+                let _ = output.write_all(line.as_bytes());
+            }
+            None => eprint!("{line}"),
+        }
+
+        let captured = CAPTURED.with(|captured| match captured.borrow_mut().as_mut() {
+            Some(captured) => {
+                captured.push_str(&line);
+                true
+            }
+            None => false,
+        });
+
+        if !captured {
+            let mut pending = self.pending.lock().unwrap();
+            pending.push_str(&line);
+
+            if pending.len() > MAX_PENDING_LEN {
+                // only whole lines are removed
+                let excess = pending.len() - MAX_PENDING_LEN;
+                let end = pending.as_bytes()[excess - 1..]
+                    .iter()
+                    .position(|&b| b == b'\n')
+                    .map_or(pending.len(), |i| excess + i);
+                pending.drain(..end);
+            }
+        }
+    }
+
+    /// takes the output that wasn't sent to a client yet
+    pub fn take_pending(&self) -> String {
+        std::mem::take(&mut self.pending.lock().unwrap())
+    }
+}
+
+static SINK: Sink = Sink::new();
+
+pub fn report_args(level: Level, kind: Kind, args: fmt::Arguments) {
+    SINK.report(level, kind, args)
+}
+
+/// writes all reported output to `log` as well
+pub fn log_to(log: impl Write + Send + 'static) {
+    SINK.log_to(Box::new(log))
+}
+
+/// writes output to `output` instead of stderr
+pub fn output_to(output: impl Write + Send + 'static) {
+    SINK.output_to(Box::new(output))
+}
+
+pub fn set_level(level: Level) {
+    SINK.set_level(level)
+}
+
+pub fn take_pending() -> String {
+    SINK.take_pending()
+}
+
+/// collects the output reported on the current thread while calling `f`
+pub fn capture<T>(f: impl FnOnce() -> T) -> (T, String) {
+    let previous = CAPTURED.with(|captured| captured.replace(Some(String::new())));
+    let res = f();
+    let output = CAPTURED.with(|captured| captured.replace(previous));
+
+    (res, output.unwrap_or_default())
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        std::{
+            io,
+            sync::{Arc, Barrier},
+            thread,
+        },
+    };
+
+    #[derive(Clone, Default)]
+    struct SharedLog(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedLog {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn concurrent_clients() {
+        let sink = Sink::new();
+        let barrier = Barrier::new(3);
+
+        let (a, b) = thread::scope(|s| {
+            let client = |name: &'static str| {
+                let (sink, barrier) = (&sink, &barrier);
+
+                s.spawn(move || {
+                    capture(|| {
+                        for i in 0..3 {
+                            sink.report(Level::Info, Kind::General, format_args!("{name} {i}"));
+                            barrier.wait();
+                        }
+                    })
+                    .1
+                })
+            };
+
+            let (a, b) = (client("a"), client("b"));
+
+            for i in 0..3 {
+                sink.report(Level::Info, Kind::General, format_args!("background {i}"));
+                barrier.wait();
+            }
+
+            (a.join().unwrap(), b.join().unwrap())
+        });
+
+        assert_eq!(a, "a 0\na 1\na 2\n");
+        assert_eq!(b, "b 0\nb 1\nb 2\n");
+        assert_eq!(
+            sink.take_pending(),
+            "background 0\nbackground 1\nbackground 2\n"
+        );
+        assert_eq!(sink.take_pending(), "")
+    }
+
+    #[test]
+    fn pending_output_is_limited() {
+        let sink = Sink::new();
+        let line = "x".repeat(1023);
+
+        for _ in 0..=MAX_PENDING_LEN / 1024 {
+            sink.report(Level::Info, Kind::General, format_args!("{line}"))
+        }
+
+        let pending = sink.take_pending();
+        assert_eq!(pending.len(), MAX_PENDING_LEN);
+        assert!(pending.starts_with('x'))
+    }
+
+    #[test]
+    fn nested_capture() {
+        let sink = Sink::new();
+
+        let ((_, inner), outer) = capture(|| {
+            sink.report(Level::Info, Kind::General, format_args!("outer"));
+            capture(|| sink.report(Level::Info, Kind::General, format_args!("inner")))
+        });
+
+        assert_eq!(inner, "inner\n");
+        assert_eq!(outer, "outer\n");
+        assert_eq!(sink.take_pending(), "")
+    }
+
+    #[test]
+    fn logs_records() {
+        let sink = Sink::new();
+        let log = SharedLog::default();
+        sink.log_to(Box::new(log.clone()));
+
+        let (_, output) = capture(|| {
+            sink.report(Level::Debug, Kind::Focus, format_args!("focused game.exe"));
+            sink.report(Level::Error, Kind::Driver, format_args!("couldn't write"))
+        });
+
+        // debug records are only logged
+        assert_eq!(output, "couldn't write\n");
+
+        sink.set_level(Level::Debug);
+        let (_, output) =
+            capture(|| sink.report(Level::Debug, Kind::Rule, format_args!("selected game.exe")));
+        assert_eq!(output, "selected game.exe\n");
+
+        let log = String::from_utf8(log.0.lock().unwrap().clone()).unwrap();
+        let records = log
+            .lines()
+            .map(|line| {
+                let record = Record::parse(line).unwrap();
+                (record.level, record.kind, record.message)
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(records, [
+            (Level::Debug, Kind::Focus, "focused game.exe".into()),
+            (Level::Error, Kind::Driver, "couldn't write".into()),
+            (Level::Debug, Kind::Rule, "selected game.exe".into())
+        ])
+    }
+}
@@ -0,0 +1,44 @@
+use {
+    super::util::validate,
+    std::{fs::File, io, mem::MaybeUninit, os::windows::io::AsRawHandle},
+    winapi::um::fileapi::GetFileInformationByHandle,
+};
+
+/// identifies a file independently of its path, by its volume and index
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct FileId {
+    volume: u32,
+    index: u64,
+}
+
+pub fn file_id(file: &File) -> io::Result<FileId> {
+    let mut info = MaybeUninit::uninit();
+
+    validate(unsafe {
+        GetFileInformationByHandle(file.as_raw_handle().cast(), info.as_mut_ptr())
+    })?;
+    let info = unsafe { info.assume_init() };
+
+    Ok(FileId {
+        volume: info.dwVolumeSerialNumber,
+        index: (info.nFileIndexHigh as u64) << 32 | info.nFileIndexLow as u64,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use {super::*, std::env};
+
+    #[test]
+    fn file_ids() {
+        let exe = env::current_exe().unwrap();
+        let other = env::temp_dir().join(format!("senscale-test-{}-file-id", std::process::id()));
+        std::fs::write(&other, "").unwrap();
+
+        let id = |path: &std::path::Path| file_id(&File::open(path).unwrap()).unwrap();
+        assert_eq!(id(&exe), id(&exe));
+        assert_ne!(id(&exe), id(&other));
+
+        std::fs::remove_file(other).unwrap()
+    }
+}
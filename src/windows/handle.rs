@@ -0,0 +1,49 @@
+use {
+    std::io,
+    winapi::um::{
+        handleapi::{CloseHandle, INVALID_HANDLE_VALUE},
+        winnt::HANDLE,
+    },
+};
+
+/// an owned handle that is closed when dropped
+#[derive(Debug)]
+pub struct Handle(HANDLE);
+
+impl Handle {
+    /// takes ownership of a handle, treating null and invalid handles as
+    /// failures of the function that returned them
+    pub fn new(raw: HANDLE) -> io::Result<Handle> {
+        if raw.is_null() || raw == INVALID_HANDLE_VALUE {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(Handle(raw))
+        }
+    }
+
+    pub fn raw(&self) -> HANDLE {
+        self.0
+    }
+}
+
+impl Drop for Handle {
+    fn drop(&mut self) {
+        unsafe {
+            CloseHandle(self.0);
+        }
+    }
+}
+
+// handles aren't tied to the thread that opened them
+unsafe impl Send for Handle {}
+
+#[cfg(test)]
+mod test {
+    use {super::*, std::ptr};
+
+    #[test]
+    fn rejects_invalid() {
+        assert!(Handle::new(ptr::null_mut()).is_err());
+        assert!(Handle::new(INVALID_HANDLE_VALUE).is_err())
+    }
+}
@@ -1,3 +1,6 @@
+pub mod file;
+pub mod handle;
 pub mod process;
 pub mod thread;
 pub mod util;
+pub mod window;
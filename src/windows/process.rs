@@ -1,26 +1,312 @@
 use {
-    super::util::validate,
-    std::{ffi::OsString, io, os::windows::prelude::OsStringExt, path::PathBuf},
+    super::{
+        handle::Handle,
+        util::{uninit_sized, validate},
+    },
+    std::{
+        ffi::OsString,
+        fmt,
+        io,
+        os::windows::prelude::OsStringExt,
+        path::PathBuf,
+        ptr,
+        slice,
+        time::Duration,
+    },
     winapi::{
-        shared::minwindef::MAX_PATH,
+        shared::{
+            minwindef::{FILETIME, MAX_PATH},
+            sddl::ConvertSidToStringSidW,
+            winerror::{
+                ERROR_ACCESS_DENIED,
+                ERROR_INSUFFICIENT_BUFFER,
+                ERROR_INVALID_PARAMETER,
+                WAIT_TIMEOUT,
+            },
+        },
         um::{
-            handleapi::CloseHandle,
-            processthreadsapi::OpenProcess,
-            winbase::QueryFullProcessImageNameW,
-            winnt::PROCESS_QUERY_LIMITED_INFORMATION,
+            processthreadsapi::{
+                GetCurrentProcess,
+                GetProcessTimes,
+                OpenProcess,
+                OpenProcessToken,
+                ProcessIdToSessionId,
+            },
+            securitybaseapi::GetTokenInformation,
+            synchapi::WaitForSingleObject,
+            tlhelp32::{
+                CreateToolhelp32Snapshot,
+                Process32FirstW,
+                Process32NextW,
+                PROCESSENTRY32W,
+                TH32CS_SNAPPROCESS,
+            },
+            winbase::{LocalFree, QueryFullProcessImageNameW, WAIT_OBJECT_0},
+            winnt::{
+                TokenUser,
+                PROCESS_QUERY_LIMITED_INFORMATION,
+                SYNCHRONIZE,
+                TOKEN_QUERY,
+                TOKEN_USER,
+                UNICODE_STRING_MAX_CHARS,
+            },
         },
     },
 };
 
-pub fn exe_path(id: u32) -> io::Result<PathBuf> {
-    let proc = unsafe { OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false.into(), id) };
+#[derive(Debug)]
+pub enum Error {
+    /// the process exists, but we're not allowed to access it (e.g. because
+    /// it's elevated or protected)
+    AccessDenied,
+    /// there's no process with the given id
+    NotFound,
+    Other(io::Error),
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        match e.raw_os_error().map(|code| code as u32) {
+            Some(ERROR_ACCESS_DENIED) => Error::AccessDenied,
+            _ => Error::Other(e),
+        }
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::AccessDenied => io::ErrorKind::PermissionDenied.into(),
+            Error::NotFound => io::ErrorKind::NotFound.into(),
+            Error::Other(e) => e,
+        }
+    }
+}
 
-    let mut buf = [0; MAX_PATH];
-    let mut end = MAX_PATH as _;
-    validate(unsafe { QueryFullProcessImageNameW(proc, 0, buf.as_mut_ptr(), &mut end) })?;
-    unsafe {
-        CloseHandle(proc);
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::AccessDenied => "access denied".fmt(f),
+            Error::NotFound => "process not found".fmt(f),
+            Error::Other(e) => e.fmt(f),
+        }
     }
+}
+
+impl std::error::Error for Error {}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+fn open(id: u32, access: u32) -> Result<Handle> {
+    Handle::new(unsafe { OpenProcess(access, false.into(), id) }).map_err(|e| {
+        // only means the process doesn't exist here, other functions return it
+        // for actually invalid parameters
+        if e.raw_os_error() == Some(ERROR_INVALID_PARAMETER as _) {
+            Error::NotFound
+        } else {
+            e.into()
+        }
+    })
+}
+
+pub fn exe_path(id: u32) -> Result<PathBuf> {
+    let proc = open(id, PROCESS_QUERY_LIMITED_INFORMATION)?;
+
+    let mut buf = vec![0; MAX_PATH];
+
+    loop {
+        let mut end = buf.len() as _;
+        let res = validate(unsafe {
+            QueryFullProcessImageNameW(proc.raw(), 0, buf.as_mut_ptr(), &mut end)
+        });
+
+        match res {
+            Ok(()) => return Ok(OsString::from_wide(&buf[..end as usize]).into()),
+            Err(e)
+                if e.raw_os_error() == Some(ERROR_INSUFFICIENT_BUFFER as _)
+                    && buf.len() < UNICODE_STRING_MAX_CHARS as usize =>
+            {
+                buf.resize(buf.len() * 2, 0)
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+/// the id of the terminal services session the process belongs to
+pub fn session_id(id: u32) -> Result<u32> {
+    let mut session = 0;
+    validate(unsafe { ProcessIdToSessionId(id, &mut session) })?;
+    Ok(session)
+}
+
+/// the security identifier of the user the current process runs as, like
+/// S-1-5-21-...
+pub fn current_user_sid() -> io::Result<String> {
+    let mut token = ptr::null_mut();
+    validate(unsafe { OpenProcessToken(GetCurrentProcess(), TOKEN_QUERY, &mut token) })?;
+    let token = Handle::new(token)?;
+
+    // the first call only determines the size
+    let mut len = 0;
+    unsafe { GetTokenInformation(token.raw(), TokenUser, ptr::null_mut(), 0, &mut len) };
+
+    // u64s, so the buffer is aligned for the pointer in TOKEN_USER
+    let mut buf = vec![0u64; (len as usize).div_ceil(8)];
+    validate(unsafe {
+        GetTokenInformation(
+            token.raw(),
+            TokenUser,
+            buf.as_mut_ptr().cast(),
+            len,
+            &mut len,
+        )
+    })?;
+    let user = unsafe { &*buf.as_ptr().cast::<TOKEN_USER>() };
+
+    let mut sid = ptr::null_mut();
+    validate(unsafe { ConvertSidToStringSidW(user.User.Sid, &mut sid) })?;
+
+    let len = (0..).take_while(|&i| unsafe { *sid.add(i) } != 0).count();
+    let string = OsString::from_wide(unsafe { slice::from_raw_parts(sid, len) });
+    unsafe { LocalFree(sid.cast()) };
+
+    Ok(string.to_string_lossy().into_owned())
+}
+
+/// when the process was created, which tells it apart from processes that
+/// had or will have the same id
+pub fn creation_time(id: u32) -> Result<u64> {
+    let proc = open(id, PROCESS_QUERY_LIMITED_INFORMATION)?;
+    let empty = FILETIME {
+        dwLowDateTime: 0,
+        dwHighDateTime: 0,
+    };
+    let [mut created, mut exited, mut kernel, mut user] = [empty; 4];
+
+    validate(unsafe {
+        GetProcessTimes(
+            proc.raw(),
+            &mut created,
+            &mut exited,
+            &mut kernel,
+            &mut user,
+        )
+    })?;
+
+    Ok((created.dwHighDateTime as u64) << 32 | created.dwLowDateTime as u64)
+}
+
+/// a handle that can be used to wait for a process to exit
+pub struct Exit(Handle);
 
-    Ok(OsString::from_wide(&buf[..end as usize]).into())
+impl Exit {
+    pub fn open(id: u32) -> Result<Exit> {
+        open(id, SYNCHRONIZE).map(Exit)
+    }
+
+    /// waits for the process to exit for at most `timeout` and returns
+    /// whether it did
+    pub fn wait(&self, timeout: Duration) -> io::Result<bool> {
+        let millis = timeout.as_millis().try_into().unwrap_or(u32::MAX);
+
+        match unsafe { WaitForSingleObject(self.0.raw(), millis) } {
+            WAIT_OBJECT_0 => Ok(true),
+            WAIT_TIMEOUT => Ok(false),
+            _ => Err(io::Error::last_os_error()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProcessInfo {
+    pub id: u32,
+    /// the file name of the process' executable
+    pub exe_name: PathBuf,
+}
+
+/// lists all processes currently running on the system
+pub fn snapshot() -> io::Result<Vec<ProcessInfo>> {
+    let snapshot = Handle::new(unsafe { CreateToolhelp32Snapshot(TH32CS_SNAPPROCESS, 0) })?;
+
+    let mut entry = unsafe { uninit_sized::<PROCESSENTRY32W>(|e| &mut e.dwSize) };
+    let mut processes = Vec::new();
+    let mut found = unsafe { Process32FirstW(snapshot.raw(), &mut entry) };
+
+    while found != 0 {
+        let name = &entry.szExeFile;
+        let len = name.iter().position(|&c| c == 0).unwrap_or(name.len());
+
+        processes.push(ProcessInfo {
+            id: entry.th32ProcessID,
+            exe_name: OsString::from_wide(&name[..len]).into(),
+        });
+
+        found = unsafe { Process32NextW(snapshot.raw(), &mut entry) };
+    }
+
+    Ok(processes)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn current_exe_path() {
+        assert_eq!(
+            std::env::current_exe().unwrap(),
+            exe_path(std::process::id()).unwrap()
+        )
+    }
+
+    #[test]
+    fn missing_process() {
+        // process ids are multiples of 4, so this one can't exist
+        assert!(matches!(exe_path(3), Err(Error::NotFound)));
+        assert!(matches!(creation_time(3), Err(Error::NotFound)))
+    }
+
+    #[test]
+    fn typed_errors() {
+        let error = |code| Error::from(io::Error::from_raw_os_error(code as _));
+
+        assert!(matches!(error(ERROR_ACCESS_DENIED), Error::AccessDenied));
+        assert!(matches!(error(ERROR_INVALID_PARAMETER), Error::Other(_)));
+        assert!(matches!(error(ERROR_INSUFFICIENT_BUFFER), Error::Other(_)))
+    }
+
+    #[test]
+    fn snapshot_contains_current_process() {
+        let current = snapshot()
+            .unwrap()
+            .into_iter()
+            .find(|proc| proc.id == std::process::id())
+            .unwrap();
+
+        assert_eq!(
+            std::env::current_exe().unwrap().file_name(),
+            Some(current.exe_name.as_os_str())
+        )
+    }
+
+    #[test]
+    fn sid_of_current_user() {
+        assert!(current_user_sid().unwrap().starts_with("S-1-"))
+    }
+
+    #[test]
+    fn creation_time_of_current_process() {
+        let created = creation_time(std::process::id()).unwrap();
+
+        assert!(created > 0);
+        assert_eq!(creation_time(std::process::id()).unwrap(), created)
+    }
+
+    #[test]
+    fn exit_of_running_process() {
+        let exit = Exit::open(std::process::id()).unwrap();
+
+        assert!(!exit.wait(Duration::ZERO).unwrap())
+    }
 }
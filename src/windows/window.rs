@@ -0,0 +1,46 @@
+use {
+    std::{ffi::OsString, os::windows::ffi::OsStringExt},
+    winapi::{
+        shared::windef::HWND,
+        um::winuser::{GetClassNameW, GetWindowTextLengthW, GetWindowTextW},
+    },
+};
+
+/// the longest class name windows allows
+const MAX_CLASS_NAME_LEN: usize = 256;
+
+/// a top-level window, which may have been destroyed since
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Window(usize);
+
+impl Window {
+    pub fn new(handle: HWND) -> Window {
+        Window(handle as _)
+    }
+
+    fn handle(self) -> HWND {
+        self.0 as _
+    }
+
+    /// the current title, which is empty if the window doesn't exist anymore
+    pub fn title(self) -> String {
+        let len = unsafe { GetWindowTextLengthW(self.handle()) };
+        let mut buf = vec![0; len as usize + 1];
+        let len = unsafe { GetWindowTextW(self.handle(), buf.as_mut_ptr(), buf.len() as _) };
+
+        from_wide(&buf[..len as usize])
+    }
+
+    /// the name of the window's class, which is empty if the window doesn't
+    /// exist anymore
+    pub fn class(self) -> String {
+        let mut buf = [0; MAX_CLASS_NAME_LEN];
+        let len = unsafe { GetClassNameW(self.handle(), buf.as_mut_ptr(), buf.len() as _) };
+
+        from_wide(&buf[..len as usize])
+    }
+}
+
+fn from_wide(wide: &[u16]) -> String {
+    OsString::from_wide(wide).to_string_lossy().into_owned()
+}
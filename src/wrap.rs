@@ -1,163 +1,217 @@
 use {
     crate::{
-        cfg::{self, EditableConfig},
+        cfg,
+        cli,
+        instance::{self, Record},
+        log,
         msg,
-        thread_id::ThreadId,
-        windows::thread,
         Result,
     },
     std::{
         env,
-        fs::{self, File, OpenOptions},
-        io::{self, BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+        fs::{self, File},
+        io::{self, BufRead},
         os::windows::process::CommandExt,
         path::{Path, PathBuf},
         process::Command,
+        thread,
+        time::{Duration, Instant, SystemTime},
     },
     winapi::um::winbase::{CREATE_NEW_PROCESS_GROUP, DETACHED_PROCESS},
 };
 
-pub fn run() -> io::Result<()> {
-    if instance_file().exists() {
-        if already_running()? {
-            println!("already running");
-            return Ok(())
-        } else {
-            clean()
-        }
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(10);
+const STARTUP_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+pub fn run(timeout: Duration, verbose: bool) -> io::Result<()> {
+    if instance::is_locked(instance::lock_file())? {
+        println!("already running");
+        return Ok(())
     }
 
-    let mut child = Child::spawn()?;
-    child.wait_for_output()?;
-    child.save()
-}
+    clean();
 
-#[derive(Debug, PartialEq, Eq)]
-struct Child {
-    thread_id: ThreadId,
-    output_pos: u64,
+    if spawn_child(verbose)? {
+        // the output produced during initialization
+        send_msg_with_response(msg::Server::TakeOutput, timeout)
+    } else {
+        io::copy(&mut output_file_read()?, &mut io::stdout()).map(drop)
+    }
 }
 
-impl Child {
-    fn spawn() -> io::Result<Child> {
-        Command::new(env::current_exe()?)
-            .arg("run")
-            .arg("--foreground")
-            .arg("--parent-thread")
-            .arg(thread::current_id().to_string())
-            .stderr(output_file_write()?)
-            .creation_flags(CREATE_NEW_PROCESS_GROUP | DETACHED_PROCESS)
-            .spawn()?;
-
-        let thread_id = if let Some(msg::Client::Running { msg_thread }) = msg::wait() {
-            msg_thread
-        } else {
+/// spawns a child process running in the background and waits for it to
+/// initialize. returns `false` if the child exited during initialization.
+fn spawn_child(verbose: bool) -> io::Result<bool> {
+    let mut command = Command::new(env::current_exe()?);
+    command.arg("run").arg("--foreground");
+
+    if verbose {
+        command.arg("--verbose");
+    }
+
+    // stderr is still redirected for anything that's not reported, like errors
+    // during startup
+    let mut process = command
+        .arg("--output")
+        .arg(output_file_path())
+        .stderr(output_file_write()?)
+        .creation_flags(CREATE_NEW_PROCESS_GROUP | DETACHED_PROCESS)
+        .spawn()?;
+
+    let deadline = Instant::now() + STARTUP_TIMEOUT;
+
+    // the child only starts listening once it's initialized
+    while !msg::is_listening(&msg::default_name()?) {
+        if process.try_wait()?.is_some() {
+            return Ok(false)
+        }
+
+        if Instant::now() > deadline {
             return Err(io::Error::new(
-                io::ErrorKind::NotConnected,
+                io::ErrorKind::TimedOut,
                 "child process did not respond",
             ))
-        };
-
-        Ok(Child {
-            thread_id,
-            output_pos: 0,
-        })
-    }
+        }
 
-    fn to_bytes(&self) -> [u8; 12] {
-        let mut out = [0; 12];
-        let (a, b) = out.split_at_mut(4);
-        a.copy_from_slice(&u32::from(self.thread_id).to_le_bytes());
-        b.copy_from_slice(&self.output_pos.to_le_bytes());
-        out
+        thread::sleep(STARTUP_POLL_INTERVAL)
     }
 
-    fn from_bytes(bytes: [u8; 12]) -> Child {
-        let (mut a, mut b) = ([0; 4], [0; 8]);
-        let (left, right) = bytes.split_at(4);
-        a.copy_from_slice(left);
-        b.copy_from_slice(right);
+    Ok(true)
+}
 
-        Child {
-            thread_id: u32::from_le_bytes(a).into(),
-            output_pos: u64::from_le_bytes(b),
+/// sends a request that's answered with output and prints it
+fn send_msg_with_response(msg: msg::Server, timeout: Duration) -> io::Result<()> {
+    match request(&msg, timeout)? {
+        msg::Client::Done { output } => {
+            print!("{output}");
+            Ok(())
         }
+        _ => Err(msg::unexpected_response()),
     }
+}
+
+/// reads the record of the running instance and makes sure it can be talked
+/// to
+fn running_instance() -> io::Result<Record> {
+    let record = Record::read(instance::record_file()).map_err(no_instance_error)?;
 
-    fn save(&self) -> io::Result<()> {
-        let mut file = File::create(instance_file())?;
-        file.write_all(&self.to_bytes())
+    if !instance::is_locked(instance::lock_file())? {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            "the instance is no longer running, run `senscale clean`",
+        ))
     }
 
-    fn load() -> io::Result<Child> {
-        let mut bytes = [0; 12];
+    record.check_compatible(&Record::current(SystemTime::now())?)?;
 
-        let mut file = File::open(instance_file()).map_err(instance_file_error)?;
+    Ok(record)
+}
 
-        file.read_exact(&mut bytes)?;
+fn request(msg: &msg::Server, timeout: Duration) -> io::Result<msg::Client> {
+    let record = running_instance()?;
+    msg::request(&msg::default_name()?, msg, timeout).map_err(not_responding(&record))
+}
 
-        Ok(Child::from_bytes(bytes))
+/// points out the process to end if the instance hangs, since it keeps the
+/// lock until it exits
+fn not_responding(record: &Record) -> impl FnOnce(io::Error) -> io::Error {
+    let pid = record.pid;
+
+    move |e| {
+        if e.kind() == io::ErrorKind::TimedOut {
+            io::Error::new(
+                e.kind(),
+                format!("{e}, end process {pid} (e.g. with `taskkill /F /PID {pid}`)"),
+            )
+        } else {
+            e
+        }
     }
+}
 
-    fn print_output(&mut self) -> io::Result<()> {
-        let mut stdout = io::stdout();
-        let mut output = output_file_read()?;
-        output.seek(SeekFrom::Start(self.output_pos))?;
+pub fn stop(timeout: Duration) -> io::Result<()> {
+    request(&msg::Server::Stop, timeout)?;
 
-        self.output_pos += io::copy(&mut output, &mut stdout)?;
+    // the output is kept, so it can still be read with `logs`
+    let _ = fs::remove_file(instance::record_file());
+    Ok(())
+}
 
-        Ok(())
-    }
+pub fn reload(timeout: Duration) -> io::Result<()> {
+    send_msg_with_response(msg::Server::Reload { print: true }, timeout)
+}
 
-    fn wait_for_output(&mut self) -> io::Result<()> {
-        if let Some(msg::Client::Printed { .. }) = msg::wait() {
-            self.print_output()
-        } else {
-            Ok(())
-        }
+pub fn status(json: bool, timeout: Duration) -> Result {
+    match request(&msg::Server::Status, timeout)? {
+        msg::Client::Status(status) if json => println!("{}", serde_json::to_string(&status)?),
+        msg::Client::Status(status) => print!("{status}"),
+        _ => return Err(msg::unexpected_response().into()),
     }
 
-    fn send(&self, msg: msg::Server) -> io::Result<()> {
-        self.thread_id.send(msg)
-    }
+    Ok(())
+}
 
-    fn send_msg_with_response(msg: msg::Server) -> io::Result<()> {
-        let mut child = Child::load()?;
-        child.send(msg)?;
-        child.wait_for_output()?;
-        child.save()
-    }
+pub fn set(args: cli::Set, timeout: Duration) -> Result {
+    let msg = match (args.sensitivity, args.clear) {
+        (Some(_), true) => return Err("can't set and clear an override at the same time".into()),
+        (None, true) => msg::Server::ClearOverride,
+        (Some(sensitivity), false) => msg::Server::Set {
+            sensitivity,
+            process: args.process,
+            duration_ms: args
+                .duration
+                .map(|duration| duration.as_millis().try_into().unwrap_or(u64::MAX)),
+        },
+        (None, false) => return Err("no sensitivity specified".into()),
+    };
+
+    send_msg_with_response(msg, timeout).map_err(<_>::into)
 }
 
-pub fn stop() -> io::Result<()> {
-    Child::load()?.send(msg::Server::Stop)?;
-    clean();
+pub fn pause(timeout: Duration) -> io::Result<()> {
+    send_msg_with_response(msg::Server::Pause, timeout)
+}
+
+pub fn resume(timeout: Duration) -> io::Result<()> {
+    send_msg_with_response(msg::Server::Resume, timeout)
+}
+
+pub fn ping(timeout: Duration) -> io::Result<()> {
+    let record = running_instance()?;
+    let elapsed = msg::ping(&msg::default_name()?, timeout).map_err(not_responding(&record))?;
+    println!("responded in {}ms", elapsed.as_millis());
     Ok(())
 }
 
-pub fn reload() -> io::Result<()> {
-    Child::send_msg_with_response(msg::Server::Reload {
-        params: msg::ReloadParams {
-            thread: thread::current_id(),
-            print: true,
-        },
-    })
+pub fn watch(timeout: Duration) -> io::Result<()> {
+    let record = running_instance()?;
+    let lines = msg::subscribe(&msg::default_name()?, timeout).map_err(not_responding(&record))?;
+
+    for line in lines {
+        println!("{}", line?)
+    }
+
+    Ok(())
 }
 
 pub fn clean() {
+    let output = output_file_path();
+
+    for rotated in log::rotated_files(&output).collect::<Vec<_>>() {
+        let _ = fs::remove_file(rotated);
+    }
+
     let _ = (
-        fs::remove_file(instance_file()),
-        fs::remove_file(output_file_path()),
+        fs::remove_file(instance::record_file()),
+        fs::remove_file(output),
     );
 }
 
-pub fn print_output() -> io::Result<()> {
-    let mut child = Child::load()?;
-    child.print_output()?;
-    child.save()
+pub fn print_output(timeout: Duration) -> io::Result<()> {
+    send_msg_with_response(msg::Server::TakeOutput, timeout)
 }
 
-fn instance_file_error(err: io::Error) -> io::Error {
+fn no_instance_error(err: io::Error) -> io::Error {
     if err.kind() == io::ErrorKind::NotFound {
         io::Error::new(err.kind(), "no instance running")
     } else {
@@ -165,10 +219,6 @@ fn instance_file_error(err: io::Error) -> io::Error {
     }
 }
 
-fn instance_file() -> PathBuf {
-    env::temp_dir().join("senscale-instance")
-}
-
 fn output_file_path() -> PathBuf {
     env::temp_dir().join("senscale-output")
 }
@@ -184,20 +234,20 @@ fn output_file_read() -> io::Result<File> {
     File::open(output_file_path())
 }
 
-pub fn adjust(process: PathBuf) -> Result {
+pub fn adjust(process: PathBuf, timeout: Duration) -> Result {
     let config = cfg::config_dir()?.file();
 
     for res in io::stdin()
         .lock()
         .lines()
-        .filter_map(io::Result::ok)
+        .map_while(io::Result::ok)
         .map_while(|line| {
             let line = line.trim();
             (!line.is_empty()).then(|| line.parse::<f64>())
         })
     {
         match res {
-            Ok(sens) => set_sens(&config, process.clone(), sens)?,
+            Ok(sens) => set_sens(&config, process.clone(), sens, timeout)?,
             Err(e) => eprintln!("failed to parse as number: {e}"),
         }
     }
@@ -205,29 +255,15 @@ pub fn adjust(process: PathBuf) -> Result {
     Ok(())
 }
 
-fn set_sens(config_path: impl AsRef<Path>, process: PathBuf, sens: f64) -> Result {
-    let mut config: EditableConfig =
-        serde_yaml::from_reader(BufReader::new(File::open(&config_path)?))?;
-
-    config
-        .processes
-        .entry(process)
-        .or_insert_with(<_>::default)
-        .sensitivity = sens;
-
-    let file = OpenOptions::new()
-        .write(true)
-        .truncate(true)
-        .open(config_path)?;
+fn set_sens(
+    config_path: impl AsRef<Path>,
+    process: PathBuf,
+    sens: f64,
+    timeout: Duration,
+) -> Result {
+    cfg::set_sensitivity(config_path, process, sens)?;
 
-    cfg::write_config(&config, BufWriter::new(file))?;
-
-    Child::send_msg_with_response(msg::Server::Reload {
-        params: msg::ReloadParams {
-            thread: thread::current_id(),
-            print: false,
-        },
-    })?;
+    send_msg_with_response(msg::Server::Reload { print: false }, timeout)?;
 
     Ok(())
 }
@@ -240,56 +276,3 @@ pub fn config() -> Result {
     print!("{}", file.display());
     Ok(())
 }
-
-fn already_running() -> io::Result<bool> {
-    let child = Child::load()?;
-
-    if child.thread_id == thread::current_id() {
-        return Ok(false)
-    }
-
-    Ok(match thread::process_exe_path(child.thread_id) {
-        Some(exe) => exe? == env::current_exe()?,
-        None => false,
-    })
-}
-
-#[cfg(test)]
-mod test {
-    use super::*;
-
-    #[test]
-    fn child_to_bytes() {
-        let thread_id = u32::MAX.into();
-        let output_pos = 9259542123273814144;
-
-        assert_eq!(
-            [255, 255, 255, 255, 128, 128, 128, 128, 128, 128, 128, 128],
-            Child {
-                thread_id,
-                output_pos
-            }
-            .to_bytes()
-        )
-    }
-
-    #[test]
-    fn child_from_bytes() {
-        assert_eq!(
-            Child {
-                thread_id: u32::MAX.into(),
-                output_pos: 9259542123273814144
-            },
-            Child::from_bytes([255, 255, 255, 255, 128, 128, 128, 128, 128, 128, 128, 128])
-        )
-    }
-
-    #[test]
-    fn child_roundtrip() {
-        let child = Child {
-            thread_id: 31267374_u32.into(),
-            output_pos: 9613725632561,
-        };
-        assert_eq!(child, Child::from_bytes(child.to_bytes()))
-    }
-}